@@ -0,0 +1,3 @@
+//! B 站接口的响应结构
+
+pub mod season;
@@ -0,0 +1,384 @@
+//! 番剧接口 `pgc/view/web/season` 的 result。
+//! 标题、剧集的 aid/cid 等必需字段缺失或类型不符时解析失败并指出字段路径，
+//! 其余字段缺失时取默认值；接口中的 null 数组视为空。
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Season {
+    pub season_id: i64,
+    pub title: String,
+    #[serde(default)]
+    pub season_title: String,
+    #[serde(default)]
+    pub cover: String,
+    /// 简介
+    #[serde(default)]
+    pub evaluate: String,
+    #[serde(default)]
+    pub season_type_name: String,
+    #[serde(default)]
+    pub publish: Publish,
+    #[serde(default)]
+    pub rating: Option<Rating>,
+    #[serde(default)]
+    pub stat: Stat,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub areas: Vec<Area>,
+    #[serde(default)]
+    pub rights: Rights,
+    /// 正片
+    #[serde(deserialize_with = "null_as_default")]
+    pub episodes: Vec<Episode>,
+    /// PV、特别篇等
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub section: Vec<Section>,
+    /// 同一系列的各季、剧场版与 OVA（含当前这一季）
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub seasons: Vec<SeasonRef>,
+    #[serde(default)]
+    pub series: Option<Series>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Publish {
+    /// 1 为完结
+    #[serde(default)]
+    pub is_finish: i32,
+    #[serde(default)]
+    pub is_started: i32,
+    /// 形如 2023-09-29 23:00:00
+    #[serde(default)]
+    pub pub_time: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Rating {
+    #[serde(default)]
+    pub score: f64,
+    #[serde(default)]
+    pub count: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Stat {
+    #[serde(default)]
+    pub views: i64,
+    #[serde(default)]
+    pub danmakus: i64,
+    #[serde(default)]
+    pub favorites: i64,
+    #[serde(default)]
+    pub coins: i64,
+    #[serde(default)]
+    pub likes: i64,
+    #[serde(default)]
+    pub reply: i64,
+    #[serde(default)]
+    pub share: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Area {
+    #[serde(default)]
+    pub id: i64,
+    #[serde(default)]
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Rights {
+    #[serde(default)]
+    pub allow_download: i32,
+    #[serde(default)]
+    pub area_limit: i32,
+    #[serde(default)]
+    pub is_preview: i32,
+    #[serde(default)]
+    pub only_vip_download: i32,
+}
+
+/// 角标，如 会员、限免、预告
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BadgeInfo {
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub bg_color: String,
+    #[serde(default)]
+    pub bg_color_night: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Episode {
+    /// 新接口为 ep_id，旧接口只有 id
+    #[serde(default)]
+    pub ep_id: Option<u64>,
+    #[serde(default)]
+    pub id: Option<u64>,
+    pub aid: u64,
+    pub cid: u64,
+    #[serde(default)]
+    pub bvid: String,
+    /// 集数，如 "1"、"SP"
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub long_title: String,
+    #[serde(default)]
+    pub cover: String,
+    /// 毫秒
+    #[serde(default)]
+    pub duration: u64,
+    #[serde(default)]
+    pub badge: String,
+    #[serde(default)]
+    pub badge_info: Option<BadgeInfo>,
+    #[serde(default)]
+    pub skip: Option<Skip>,
+}
+
+/// 片头片尾（秒）；未标注时接口返回 0-0
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Skip {
+    #[serde(default)]
+    pub op: Option<SkipSpan>,
+    #[serde(default)]
+    pub ed: Option<SkipSpan>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct SkipSpan {
+    #[serde(default)]
+    pub start: u64,
+    #[serde(default)]
+    pub end: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Section {
+    #[serde(default)]
+    pub id: i64,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default, rename = "type")]
+    pub section_type: i32,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub episodes: Vec<Episode>,
+}
+
+/// seasons[] 中的一季
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeasonRef {
+    pub season_id: i64,
+    #[serde(default)]
+    pub media_id: i64,
+    /// 简称，如 第二季、剧场版
+    #[serde(default)]
+    pub season_title: String,
+    /// 完整标题
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub cover: String,
+    #[serde(default)]
+    pub badge: String,
+    #[serde(default)]
+    pub badge_info: Option<BadgeInfo>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Series {
+    #[serde(default)]
+    pub series_id: i64,
+    #[serde(default)]
+    pub series_title: String,
+}
+
+fn null_as_default<'de, D, T>(d: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(d)?.unwrap_or_default())
+}
+
+/// 解析番剧接口的 result；结构不符时错误信息带字段路径，如 `episodes[3].cid`
+pub fn parse(root: Value) -> Result<Season> {
+    serde_path_to_error::deserialize(root)
+        .map_err(|e| anyhow!("番剧接口结构不符 {}: {}", e.path(), e.inner()))
+}
+
+impl Season {
+    pub fn display_title(&self) -> &str {
+        if self.title.is_empty() {
+            &self.season_title
+        } else {
+            &self.title
+        }
+    }
+
+    /// publish.pub_time 的年份部分
+    pub fn year(&self) -> String {
+        let t = &self.publish.pub_time;
+        if t.len() >= 4 {
+            t.chars().take(4).collect()
+        } else {
+            String::new()
+        }
+    }
+
+    /// 完结 / 连载
+    pub fn status(&self) -> &'static str {
+        if self.publish.is_finish == 1 {
+            "完结"
+        } else {
+            "连载"
+        }
+    }
+
+    pub fn type_name(&self) -> &str {
+        if self.season_type_name.is_empty() {
+            "TV"
+        } else {
+            &self.season_type_name
+        }
+    }
+
+    /// 按 sort（从 1 开始）取正片中的某一集
+    pub fn episode(&self, sort: usize) -> Option<&Episode> {
+        sort.checked_sub(1).and_then(|i| self.episodes.get(i))
+    }
+
+    /// 正片与各 section 中的全部剧集
+    pub fn all_episodes(&self) -> impl Iterator<Item = &Episode> {
+        self.episodes
+            .iter()
+            .chain(self.section.iter().flat_map(|sec| sec.episodes.iter()))
+    }
+
+    /// 按 ep_id 查找剧集；PV、SP 等不在 episodes 中，而在 section[].episodes
+    pub fn find_episode(&self, ep_id: u64) -> Option<&Episode> {
+        self.all_episodes().find(|ep| ep.ep_id() == Some(ep_id))
+    }
+}
+
+impl Episode {
+    pub fn ep_id(&self) -> Option<u64> {
+        self.ep_id.or(self.id)
+    }
+}
+
+impl SkipSpan {
+    /// 标注了有效区间（end > start）时返回自身
+    pub fn marked(self) -> Option<Self> {
+        (self.end > self.start).then_some(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = include_str!("../../tests/fixtures/season.json");
+
+    fn fixture_root() -> Value {
+        let v: Value = serde_json::from_str(FIXTURE).unwrap();
+        v["result"].clone()
+    }
+
+    #[test]
+    fn parses_fixture() {
+        let s = parse(fixture_root()).unwrap();
+        assert_eq!(s.season_id, 45969);
+        assert_eq!(s.display_title(), "葬送的芙莉莲");
+        assert_eq!(s.year(), "2023");
+        assert_eq!(s.status(), "完结");
+        assert_eq!(s.type_name(), "TV");
+        assert_eq!(s.episodes.len(), 3);
+        assert_eq!(s.areas[0].name, "日本");
+        assert_eq!(s.rating.as_ref().map(|r| r.score), Some(9.8));
+        assert_eq!(s.stat.views, 312000000);
+        assert_eq!(s.rights.allow_download, 1);
+        assert_eq!(s.series.as_ref().unwrap().series_title, "葬送的芙莉莲");
+        assert_eq!(s.seasons.len(), 4);
+        assert_eq!(s.seasons[1].season_title, "中配版");
+    }
+
+    #[test]
+    fn parses_episode_fields() {
+        let s = parse(fixture_root()).unwrap();
+        let ep = s.episode(1).unwrap();
+        assert_eq!(ep.ep_id(), Some(778899));
+        assert_eq!(ep.aid, 1145141919);
+        assert_eq!(ep.cid, 810810);
+        assert_eq!(ep.bvid, "BV1xx411c7mD");
+        assert_eq!(ep.long_title, "冒险的结束");
+        let skip = ep.skip.clone().unwrap();
+        assert_eq!(skip.op.and_then(SkipSpan::marked).map(|r| r.end), Some(90));
+        assert!(skip.ed.and_then(SkipSpan::marked).is_none());
+        let badge = s.episode(3).unwrap().badge_info.as_ref().unwrap();
+        assert_eq!(badge.text, "会员");
+    }
+
+    #[test]
+    fn episode_sort_is_one_based() {
+        let s = parse(fixture_root()).unwrap();
+        assert!(s.episode(0).is_none());
+        assert_eq!(s.episode(3).unwrap().ep_id(), Some(778901));
+        assert!(s.episode(4).is_none());
+    }
+
+    #[test]
+    fn finds_section_episode_by_id() {
+        let s = parse(fixture_root()).unwrap();
+        assert_eq!(s.section[0].title, "PV&其他");
+        let pv = s.find_episode(790001).unwrap();
+        assert_eq!(pv.title, "PV1");
+        // 旧接口只有 id
+        assert_eq!(pv.ep_id, None);
+    }
+
+    #[test]
+    fn null_arrays_are_empty() {
+        let mut root = fixture_root();
+        root["section"] = Value::Null;
+        root["seasons"] = Value::Null;
+        let s = parse(root).unwrap();
+        assert!(s.section.is_empty());
+        assert!(s.seasons.is_empty());
+    }
+
+    #[test]
+    fn unused_fields_may_be_missing() {
+        let mut root = fixture_root();
+        root["areas"][0].as_object_mut().unwrap().remove("id");
+        let s = parse(root).unwrap();
+        assert_eq!(s.areas[0].id, 0);
+        assert_eq!(s.areas[0].name, "日本");
+    }
+
+    #[test]
+    fn schema_drift_reports_field_path() {
+        let mut root = fixture_root();
+        root["episodes"][1].as_object_mut().unwrap().remove("cid");
+        let err = parse(root).unwrap_err().to_string();
+        assert!(err.contains("episodes[1]"), "{err}");
+        assert!(err.contains("cid"), "{err}");
+
+        let mut root = fixture_root();
+        root["title"] = Value::from(42);
+        let err = parse(root).unwrap_err().to_string();
+        assert!(err.contains("title"), "{err}");
+    }
+
+    #[test]
+    fn roundtrips_through_serialize() {
+        let s = parse(fixture_root()).unwrap();
+        let again = parse(serde_json::to_value(&s).unwrap()).unwrap();
+        assert_eq!(again.episodes.len(), s.episodes.len());
+        assert_eq!(again.find_episode(790001).unwrap().cid, 900001);
+    }
+}
@@ -0,0 +1,422 @@
+//! HLS 缓存目录的任务清单（job.json）与崩溃恢复。
+//! 每个变体目录记录最近一次 FFmpeg 任务的状态；启动时和每次请求时据此清理
+//! 残留的 `.lock`、未写完的临时文件与失败的输出，之后由正常请求流程重新生成。
+//! 另按 `[cache]` 配置的容量与过期时间淘汰最久未访问的变体目录。
+
+use actix_web::{HttpRequest, HttpResponse, Responder, post, web};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::{config, hls, supervisor};
+
+pub const MANIFEST_FILE: &str = "job.json";
+const LOCK_FILE: &str = ".lock";
+/// 记录最近访问时间（Unix 秒），供跨进程重启的 LRU 淘汰使用
+const ACCESS_FILE: &str = ".access";
+/// 访问时间写盘的最小间隔，避免每个分片请求都写文件
+const ACCESS_WRITE_INTERVAL: Duration = Duration::from_secs(60);
+/// 最近访问过的目录不参与淘汰（刚生成 playlist、任务尚未登记时也不会被删）
+const EVICT_GRACE_SECS: u64 = 120;
+
+static ACCESS_WRITTEN: Lazy<Mutex<HashMap<PathBuf, Instant>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Running,
+    /// FFmpeg 正常结束
+    Complete,
+    /// FFmpeg 启动失败或退出非 0
+    Failed,
+    /// 被重启、空闲回收或进程退出中断
+    Stopped,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub status: JobStatus,
+    pub job_id: u64,
+    #[serde(default)]
+    pub pid: Option<u32>,
+    pub start_number: u64,
+    /// Unix 秒
+    pub started_at: u64,
+    #[serde(default)]
+    pub finished_at: Option<u64>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+pub fn read_manifest(dir: &Path) -> Option<Manifest> {
+    let text = fs::read_to_string(dir.join(MANIFEST_FILE)).ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+/// 先写临时文件再改名，进程中途退出也不会留下半个 job.json
+pub fn write_manifest(dir: &Path, manifest: &Manifest) {
+    let tmp = dir.join(format!("{}.tmp", MANIFEST_FILE));
+    let result = serde_json::to_vec_pretty(manifest)
+        .map_err(std::io::Error::other)
+        .and_then(|bytes| fs::write(&tmp, bytes))
+        .and_then(|_| fs::rename(&tmp, dir.join(MANIFEST_FILE)));
+    if let Err(e) = result {
+        log::warn!("写入任务清单失败 {}: {e}", dir.display());
+    }
+}
+
+/// 更新清单中的结束状态（仅当仍是同一任务时）
+pub fn finish_manifest(dir: &Path, job_id: u64, status: JobStatus, error: Option<String>) {
+    if let Some(mut m) = read_manifest(dir)
+        && m.job_id == job_id
+    {
+        m.status = status;
+        m.pid = None;
+        m.finished_at = Some(now_secs());
+        m.error = error;
+        write_manifest(dir, &m);
+    }
+}
+
+/// 校正一个变体目录。`active` 表示当前进程内有任务正在写入该目录。
+/// 返回 true 表示做过清理。
+pub fn reconcile_dir(dir: &Path, active: bool) -> bool {
+    if active || !dir.is_dir() {
+        return false;
+    }
+    let manifest = read_manifest(dir);
+    let has_lock = dir.join(LOCK_FILE).exists();
+    let stale_running = manifest
+        .as_ref()
+        .is_some_and(|m| m.status == JobStatus::Running);
+    let failed = manifest
+        .as_ref()
+        .is_some_and(|m| m.status == JobStatus::Failed);
+    let bad_playlist = !playlist_is_complete(dir);
+    if !has_lock && !stale_running && !failed && !bad_playlist {
+        return false;
+    }
+
+    if let Some(m) = manifest.as_ref().filter(|m| m.status == JobStatus::Running)
+        && let Some(pid) = m.pid
+    {
+        kill_orphan(pid, dir);
+    }
+    log::info!(
+        "清理残留缓存 {} (lock={} status={:?} playlist_ok={})",
+        dir.display(),
+        has_lock,
+        manifest.as_ref().map(|m| m.status),
+        !bad_playlist
+    );
+    let _ = fs::remove_file(dir.join(LOCK_FILE));
+    if bad_playlist {
+        let _ = fs::remove_file(dir.join("index.m3u8"));
+    }
+    // 未改名的临时分片、FFmpeg 自己的列表与未合并的小分片都是半成品
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.ends_with(".tmp") || name == "ffmpeg.m3u8" {
+                let _ = fs::remove_file(entry.path());
+            } else if name.starts_with(hls::PARTS_DIR_PREFIX) {
+                let _ = fs::remove_dir_all(entry.path());
+            }
+        }
+    }
+    if let Some(mut m) = manifest
+        && matches!(m.status, JobStatus::Running | JobStatus::Failed)
+    {
+        // 已清理过的记录改为 stopped，失败原因保留在 error 中；后续请求会照常重新启动任务
+        if m.status == JobStatus::Running {
+            m.error = Some("进程退出时任务未结束".into());
+        }
+        m.status = JobStatus::Stopped;
+        m.pid = None;
+        m.finished_at.get_or_insert(now_secs());
+        write_manifest(dir, &m);
+    }
+    true
+}
+
+/// playlist 由我们一次性生成并以 EXT-X-ENDLIST 结尾；缺失视为正常（尚未请求），
+/// 存在但未结束的多为旧版本由 FFmpeg 实时写出、被中断的列表
+fn playlist_is_complete(dir: &Path) -> bool {
+    match fs::read_to_string(dir.join("index.m3u8")) {
+        Ok(s) => s.trim_end().ends_with("#EXT-X-ENDLIST"),
+        Err(_) => true,
+    }
+}
+
+/// 终止上次进程遗留的 FFmpeg（仅在能确认 PID 仍指向写入该目录的 ffmpeg 时）
+#[cfg(target_os = "linux")]
+fn kill_orphan(pid: u32, dir: &Path) {
+    let Ok(cmdline) = fs::read(format!("/proc/{}/cmdline", pid)) else {
+        return;
+    };
+    let cmdline = String::from_utf8_lossy(&cmdline);
+    if cmdline.contains("ffmpeg") && cmdline.contains(dir.to_string_lossy().as_ref()) {
+        log::info!("终止遗留 FFmpeg pid={} dir={}", pid, dir.display());
+        let _ = std::process::Command::new("kill")
+            .arg(pid.to_string())
+            .status();
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn kill_orphan(_pid: u32, _dir: &Path) {}
+
+/// 启动时扫描整个 HLS 缓存，校正所有带清单或锁文件的目录
+pub fn recover_on_startup() {
+    let root = PathBuf::from(&config::get().api.cache_dir).join("hls");
+    let mut stack = vec![root];
+    let mut cleaned = 0usize;
+    while let Some(dir) = stack.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut is_work_dir = false;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                stack.push(path);
+            } else if let Some(name) = path.file_name().and_then(|n| n.to_str())
+                && (name == MANIFEST_FILE || name == LOCK_FILE || name == "index.m3u8")
+            {
+                is_work_dir = true;
+            }
+        }
+        if is_work_dir && reconcile_dir(&dir, false) {
+            cleaned += 1;
+        }
+    }
+    if cleaned > 0 {
+        log::info!("启动时清理了 {} 个残留的 HLS 缓存目录", cleaned);
+    }
+}
+
+/// 记录一次对变体目录的访问（拉取 playlist 或分片）
+pub fn mark_access(dir: &Path) {
+    let Ok(mut written) = ACCESS_WRITTEN.lock() else {
+        return;
+    };
+    if written
+        .get(dir)
+        .is_some_and(|t| t.elapsed() < ACCESS_WRITE_INTERVAL)
+    {
+        return;
+    }
+    if fs::write(dir.join(ACCESS_FILE), now_secs().to_string()).is_ok() {
+        written.insert(dir.to_path_buf(), Instant::now());
+    }
+}
+
+/// 最近访问时间：优先读 .access，没有则取目录及其中文件的最新修改时间
+fn last_access(dir: &Path, entries: &[fs::DirEntry]) -> u64 {
+    if let Some(t) = fs::read_to_string(dir.join(ACCESS_FILE))
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+    {
+        return t;
+    }
+    entries
+        .iter()
+        .filter_map(|e| e.metadata().ok())
+        .chain(fs::metadata(dir).ok())
+        .filter_map(|m| m.modified().ok())
+        .filter_map(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .max()
+        .unwrap_or(0)
+}
+
+struct CachedDir {
+    path: PathBuf,
+    bytes: u64,
+    last_access: u64,
+    /// 有任务在写入（本进程登记的任务或其他进程留下的 .lock）
+    active: bool,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct GcReport {
+    /// 扫描到的变体目录数
+    pub scanned: usize,
+    pub removed: usize,
+    /// 因有任务运行或刚被访问而跳过的目录数
+    pub skipped: usize,
+    pub freed_bytes: u64,
+    /// 淘汰后剩余的缓存大小
+    pub total_bytes: u64,
+}
+
+/// 列出 `cache_dir/hls/{season}/{sort}/{variant}` 下的所有变体目录
+fn scan_variant_dirs(active: &HashSet<PathBuf>) -> Vec<CachedDir> {
+    let root = PathBuf::from(&config::get().api.cache_dir).join("hls");
+    let subdirs = |dir: &Path| -> Vec<PathBuf> {
+        fs::read_dir(dir)
+            .map(|it| {
+                it.flatten()
+                    .map(|e| e.path())
+                    .filter(|p| p.is_dir())
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let mut out = Vec::new();
+    for season in subdirs(&root) {
+        for episode in subdirs(&season) {
+            for variant in subdirs(&episode) {
+                let entries: Vec<fs::DirEntry> = fs::read_dir(&variant)
+                    .map(|it| it.flatten().collect())
+                    .unwrap_or_default();
+                let bytes = entries
+                    .iter()
+                    .filter_map(|e| e.metadata().ok())
+                    .filter(|m| m.is_file())
+                    .map(|m| m.len())
+                    .sum();
+                out.push(CachedDir {
+                    last_access: last_access(&variant, &entries),
+                    active: active.contains(&variant) || variant.join(LOCK_FILE).exists(),
+                    path: variant,
+                    bytes,
+                });
+            }
+        }
+    }
+    out
+}
+
+/// 按 `[cache]` 配置淘汰：先删超过 max_age_secs 未访问的目录，
+/// 再按最近访问时间从旧到新删除，直到总大小不超过 max_cache_bytes。
+/// 有任务运行的目录永不删除。
+pub fn gc(active: &HashSet<PathBuf>) -> GcReport {
+    let cfg = &config::get().cache;
+    let now = now_secs();
+    let mut dirs = scan_variant_dirs(active);
+    dirs.sort_by_key(|d| d.last_access);
+
+    let mut report = GcReport {
+        scanned: dirs.len(),
+        total_bytes: dirs.iter().map(|d| d.bytes).sum(),
+        ..Default::default()
+    };
+    let mut removed_any = false;
+    for dir in &dirs {
+        let idle = now.saturating_sub(dir.last_access);
+        let expired = cfg.max_age_secs > 0 && idle > cfg.max_age_secs;
+        let over_size = cfg.max_cache_bytes > 0 && report.total_bytes > cfg.max_cache_bytes;
+        if !expired && !over_size {
+            continue;
+        }
+        if dir.active || idle < EVICT_GRACE_SECS {
+            report.skipped += 1;
+            continue;
+        }
+        match fs::remove_dir_all(&dir.path) {
+            Ok(()) => {
+                log::info!(
+                    "淘汰缓存 {} ({} 字节, {}s 未访问)",
+                    dir.path.display(),
+                    dir.bytes,
+                    idle
+                );
+                report.removed += 1;
+                report.freed_bytes += dir.bytes;
+                report.total_bytes -= dir.bytes;
+                removed_any = true;
+                if let Ok(mut written) = ACCESS_WRITTEN.lock() {
+                    written.remove(&dir.path);
+                }
+            }
+            Err(e) => log::warn!("删除缓存目录失败 {}: {e}", dir.path.display()),
+        }
+    }
+    if removed_any {
+        remove_empty_episodes();
+    }
+    report
+}
+
+/// 剧集目录下已无变体时连同默认变体标记一起删除，季目录为空时一并删除
+fn remove_empty_episodes() {
+    let root = PathBuf::from(&config::get().api.cache_dir).join("hls");
+    let Ok(seasons) = fs::read_dir(&root) else {
+        return;
+    };
+    for season in seasons.flatten().map(|e| e.path()) {
+        let Ok(episodes) = fs::read_dir(&season) else {
+            continue;
+        };
+        for episode in episodes.flatten().map(|e| e.path()) {
+            let has_variant = fs::read_dir(&episode)
+                .map(|mut it| it.any(|e| e.is_ok_and(|e| e.path().is_dir())))
+                .unwrap_or(true);
+            if !has_variant {
+                let _ = fs::remove_dir_all(&episode);
+            }
+        }
+        let _ = fs::remove_dir(&season);
+    }
+}
+
+/// 后台定期执行缓存淘汰；gc_interval_secs = 0 表示不自动执行
+pub fn spawn_gc() {
+    let interval = config::get().cache.gc_interval_secs;
+    if interval == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(Duration::from_secs(interval));
+        loop {
+            tick.tick().await;
+            let active = supervisor::active_dirs();
+            match tokio::task::spawn_blocking(move || gc(&active)).await {
+                Ok(r) if r.removed > 0 => log::info!(
+                    "缓存淘汰完成：删除 {} 个目录，释放 {} 字节，剩余 {} 字节",
+                    r.removed,
+                    r.freed_bytes,
+                    r.total_bytes
+                ),
+                Ok(_) => {}
+                Err(e) => log::warn!("缓存淘汰任务异常: {e}"),
+            }
+        }
+    });
+}
+
+#[post("/admin/cache/gc")]
+pub async fn cache_gc_endpoint(req: HttpRequest) -> impl Responder {
+    if let Some(denied) = crate::admin_guard(&req) {
+        return denied;
+    }
+    let active = supervisor::active_dirs();
+    match web::block(move || gc(&active)).await {
+        Ok(report) => HttpResponse::Ok().json(crate::ApiResult {
+            code: 0,
+            success: true,
+            message: String::new(),
+            data: report,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(crate::ApiResult {
+            code: 500,
+            success: false,
+            message: format!("缓存淘汰失败: {e}"),
+            data: (),
+        }),
+    }
+}
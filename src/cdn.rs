@@ -0,0 +1,164 @@
+//! CDN 镜像选择：按 `[cdn]` 配置过滤、改写 playurl 返回的主机，
+//! 再对 base_url 与 backup_url 逐个探测可用性与延迟，按结果排序供 FFmpeg 依次尝试。
+
+use futures::future::join_all;
+use std::time::{Duration, Instant};
+
+use crate::config;
+use crate::playurl::PlayurlDash;
+
+/// 单个镜像的探测超时
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// base_url 在前、backup_url 在后，去重
+pub fn candidates(base_url: &str, backup_url: Option<&[String]>) -> Vec<String> {
+    let mut urls = vec![base_url.to_string()];
+    for url in backup_url.unwrap_or_default() {
+        if !urls.contains(url) {
+            urls.push(url.clone());
+        }
+    }
+    urls
+}
+
+/// 日志中展示用的主机名
+pub fn host(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_string))
+        .unwrap_or_else(|| url.to_string())
+}
+
+/// 请求首字节测量延迟；返回 None 表示不可用（超时、403 等）
+async fn probe(client: &reqwest::Client, url: &str) -> Option<Duration> {
+    let start = Instant::now();
+    let resp = client
+        .get(url)
+        .header("Referer", "https://www.bilibili.com")
+        .header("Range", "bytes=0-0")
+        .timeout(PROBE_TIMEOUT)
+        .send()
+        .await;
+    match resp {
+        Ok(r) if r.status().is_success() => Some(start.elapsed()),
+        Ok(r) => {
+            log::warn!("CDN 不可用 {} ({})", host(url), r.status());
+            None
+        }
+        Err(e) => {
+            log::warn!("CDN 探测失败 {}: {e}", host(url));
+            None
+        }
+    }
+}
+
+/// 并发探测所有镜像，可用的按延迟从低到高排在前面，不可用的保持原顺序排在最后（仍作为兜底）
+pub async fn rank(client: &reqwest::Client, urls: Vec<String>) -> Vec<String> {
+    if urls.len() <= 1 {
+        return urls;
+    }
+    let results = join_all(urls.iter().map(|u| probe(client, u))).await;
+    let mut ranked: Vec<(Option<Duration>, String)> = results.into_iter().zip(urls).collect();
+    // None 排在 Some 之后；sort_by_key 为稳定排序
+    ranked.sort_by_key(|(latency, _)| latency.unwrap_or(Duration::MAX));
+    for (latency, url) in &ranked {
+        if let Some(latency) = latency {
+            log::debug!("CDN {} 延迟 {}ms", host(url), latency.as_millis());
+        }
+    }
+    ranked.into_iter().map(|(_, url)| url).collect()
+}
+
+/// 主机匹配规则：含 `*` 时按通配符匹配（如 `upos-sz-*`），否则匹配该主机及其子域名
+fn host_matches(host: &str, pattern: &str) -> bool {
+    let pattern = pattern.trim().to_ascii_lowercase();
+    if pattern.is_empty() {
+        return false;
+    }
+    if !pattern.contains('*') {
+        return host == pattern || host.ends_with(&format!(".{}", pattern));
+    }
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !host.starts_with(first) || !host[first.len()..].ends_with(last) {
+        return false;
+    }
+    // 中间片段依次出现即可
+    let mut rest = &host[first.len()..host.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// 对一组镜像应用 deny / allow / replace_host。
+/// 过滤后为空时保留原列表，避免配置不当导致完全无法播放。
+fn apply_rules(urls: Vec<String>) -> Vec<String> {
+    let cfg = &config::get().cdn;
+    let host_of = |url: &str| host(url).to_ascii_lowercase();
+    let kept: Vec<String> = urls
+        .iter()
+        .filter(|u| !cfg.deny.iter().any(|p| host_matches(&host_of(u), p)))
+        .filter(|u| cfg.allow.is_empty() || cfg.allow.iter().any(|p| host_matches(&host_of(u), p)))
+        .cloned()
+        .collect();
+    let mut out = if kept.is_empty() {
+        log::warn!("[cdn] 规则过滤掉了全部 {} 个地址，保留原地址", urls.len());
+        urls
+    } else {
+        kept
+    };
+    if !cfg.replace_host.is_empty() {
+        out = out
+            .into_iter()
+            .map(|u| replace_host(&u, &cfg.replace_host))
+            .fold(Vec::new(), |mut acc, u| {
+                if !acc.contains(&u) {
+                    acc.push(u);
+                }
+                acc
+            });
+    }
+    out
+}
+
+/// 替换 URL 的主机（含端口，PCDN 地址常带非标准端口）
+fn replace_host(url: &str, new_host: &str) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(mut u) => {
+            if u.set_host(Some(new_host)).is_err() || u.set_port(None).is_err() {
+                return url.to_string();
+            }
+            u.to_string()
+        }
+        Err(_) => url.to_string(),
+    }
+}
+
+/// 对 playurl 返回的所有音视频轨（含 durl 分段）应用 `[cdn]` 规则，改写后的首个地址作为 base_url
+pub fn rewrite_dash(dash: &mut PlayurlDash) {
+    let cfg = &config::get().cdn;
+    if cfg.allow.is_empty() && cfg.deny.is_empty() && cfg.replace_host.is_empty() {
+        return;
+    }
+    let rewrite = |base_url: &mut String, backup_url: &mut Option<Vec<String>>| {
+        let mut urls = apply_rules(candidates(base_url, backup_url.as_deref())).into_iter();
+        if let Some(first) = urls.next() {
+            *base_url = first;
+        }
+        let rest: Vec<String> = urls.collect();
+        *backup_url = (!rest.is_empty()).then_some(rest);
+    };
+    for v in &mut dash.video {
+        rewrite(&mut v.base_url, &mut v.backup_url);
+    }
+    for a in &mut dash.audio {
+        rewrite(&mut a.base_url, &mut a.backup_url);
+    }
+    for seg in dash.durl.iter_mut().flat_map(|d| d.segments.iter_mut()) {
+        rewrite(&mut seg.url, &mut seg.backup_url);
+    }
+}
@@ -13,6 +13,60 @@ bind = "127.0.0.1:8080"
 public_base = "http://127.0.0.1:8080"
 # 缓存目录（部分接口可能用到）
 cache_dir = "cache"
+# 管理接口（/jobs、/admin/*）的令牌，请求需带 `Authorization: Bearer <令牌>`；留空时管理接口禁用（反向代理后无法区分本机请求）
+admin_token = ""
+
+[hls]
+# 默认分片格式：ts（MPEG-TS，非 AVC 轨转码为 H.264）或 fmp4（HEVC/AV1 直接复制）
+# 单次请求可通过 ?format=ts|fmp4 覆盖
+segment_format = "ts"
+# 同时运行的 FFmpeg 任务上限，超出的请求排队（0 为不限制）
+max_concurrent_jobs = 2
+# 任务超过该秒数无人拉取分片则自动终止（0 为不终止）
+idle_timeout_secs = 60
+# 默认画质上限（qn，如 116=1080P60、80=1080P、64=720P；0 为最高），可用 ?qn= 覆盖
+default_qn = 0
+# 默认视频编码：avc / hevc / av1（留空为不限），可用 ?codec= 覆盖
+default_codec = ""
+# 默认音轨：standard / dolby / flac，可用 ?audio= 覆盖；不存在时退回普通音轨
+default_audio = "standard"
+
+[cache]
+# HLS 缓存（cache_dir/hls）总大小上限（字节），超出时按最近访问时间淘汰（0 为不限制）
+max_cache_bytes = 10737418240
+# 超过该秒数未访问的变体缓存会被删除（0 为不过期）
+max_age_secs = 604800
+# 后台淘汰的执行间隔（秒，0 为不自动执行；仍可用 `selfani cache gc` 或 POST /admin/cache/gc 手动执行）
+gc_interval_secs = 600
+
+[season]
+# 番剧信息（pgc/view/web/season）与普通视频稿件信息（x/web-interface/view）的内存缓存秒数，同一番剧或稿件的并发请求会合并为一次（0 为不缓存）
+ttl_secs = 600
+# 是否把番剧信息写入 cache_dir/season，重启后仍可命中缓存
+persist = false
+
+[danmaku]
+# ASS 弹幕：标准字号（1080p 画布上的像素，至少为 1）、字体、不透明度（0-1）
+font_size = 50
+font_name = "Microsoft YaHei"
+opacity = 0.8
+# 弹幕可占用的屏幕高度比例（0-1），轨道放不下的弹幕会被丢弃
+density = 1.0
+# 滚动弹幕横穿屏幕的秒数、顶部/底部弹幕停留秒数（均应大于 0）
+scroll_secs = 8.0
+fixed_secs = 4.0
+# 屏蔽词：包含即屏蔽；写成 "/正则/" 按正则匹配
+filter = []
+
+[cdn]
+# 按主机过滤 / 改写播放地址（base_url 与 backup_url），对番剧与普通视频均生效。
+# 规则写主机名（同时匹配其子域名），或含 * 的通配符，如 "upos-sz-*"
+# 丢弃这些主机的地址，例如 ["mcdn.bilivideo.cn"] 可避开 PCDN
+deny = []
+# 非空时只保留这些主机的地址
+allow = []
+# 非空时把剩余地址的主机统一替换为该主机，例如 "upos-sz-mirrorali.bilivideo.com"
+replace_host = ""
 
 [cookies]
 # 登录 cookies 文件路径（程序会在扫码后写入）
@@ -31,6 +85,9 @@ pub struct ApiConfig {
     /// 缓存目录
     #[serde(default = "default_cache_dir")]
     pub cache_dir: String,
+    /// 管理接口令牌；为空时管理接口一律返回 403
+    #[serde(default)]
+    pub admin_token: String,
 }
 
 fn default_bind() -> String {
@@ -68,6 +125,211 @@ impl Default for StorageConfig {
     }
 }
 
+#[derive(Debug, Deserialize, Clone)]
+pub struct HlsConfig {
+    /// 默认分片格式：ts 或 fmp4
+    #[serde(default = "default_segment_format")]
+    pub segment_format: String,
+    /// 同时运行的 FFmpeg 任务上限，0 为不限制
+    #[serde(default = "default_max_concurrent_jobs")]
+    pub max_concurrent_jobs: usize,
+    /// 无人拉取分片多少秒后终止任务，0 为不终止
+    #[serde(default = "default_idle_timeout_secs")]
+    pub idle_timeout_secs: u64,
+    /// 默认画质上限，0 为最高
+    #[serde(default)]
+    pub default_qn: i32,
+    /// 默认视频编码 avc/hevc/av1，空为不限
+    #[serde(default)]
+    pub default_codec: String,
+    /// 默认音轨 standard/dolby/flac
+    #[serde(default = "default_audio")]
+    pub default_audio: String,
+}
+
+fn default_segment_format() -> String {
+    "ts".to_string()
+}
+fn default_max_concurrent_jobs() -> usize {
+    2
+}
+fn default_idle_timeout_secs() -> u64 {
+    60
+}
+fn default_audio() -> String {
+    "standard".to_string()
+}
+
+impl Default for HlsConfig {
+    fn default() -> Self {
+        Self {
+            segment_format: default_segment_format(),
+            max_concurrent_jobs: default_max_concurrent_jobs(),
+            idle_timeout_secs: default_idle_timeout_secs(),
+            default_qn: 0,
+            default_codec: String::new(),
+            default_audio: default_audio(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct CacheConfig {
+    /// HLS 缓存总大小上限（字节），0 为不限制
+    #[serde(default = "default_max_cache_bytes")]
+    pub max_cache_bytes: u64,
+    /// 未访问超过多少秒即删除，0 为不过期
+    #[serde(default = "default_max_age_secs")]
+    pub max_age_secs: u64,
+    /// 后台淘汰间隔（秒），0 为不自动执行
+    #[serde(default = "default_gc_interval_secs")]
+    pub gc_interval_secs: u64,
+}
+
+fn default_max_cache_bytes() -> u64 {
+    10 * 1024 * 1024 * 1024
+}
+fn default_max_age_secs() -> u64 {
+    7 * 24 * 3600
+}
+fn default_gc_interval_secs() -> u64 {
+    600
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            max_cache_bytes: default_max_cache_bytes(),
+            max_age_secs: default_max_age_secs(),
+            gc_interval_secs: default_gc_interval_secs(),
+        }
+    }
+}
+
+/// 番剧信息缓存
+#[derive(Debug, Deserialize, Clone)]
+pub struct SeasonConfig {
+    /// 内存缓存秒数，0 为不缓存；普通视频稿件信息沿用同一时长
+    #[serde(default = "default_season_ttl_secs")]
+    pub ttl_secs: u64,
+    /// 是否落盘到 cache_dir/season
+    #[serde(default)]
+    pub persist: bool,
+}
+
+fn default_season_ttl_secs() -> u64 {
+    600
+}
+
+impl Default for SeasonConfig {
+    fn default() -> Self {
+        Self {
+            ttl_secs: default_season_ttl_secs(),
+            persist: false,
+        }
+    }
+}
+
+/// 弹幕输出（ASS 排布与屏蔽词）
+#[derive(Debug, Deserialize, Clone)]
+pub struct DanmakuConfig {
+    #[serde(default = "default_dm_font_size")]
+    pub font_size: u32,
+    #[serde(default = "default_dm_font_name")]
+    pub font_name: String,
+    /// 不透明度 0-1
+    #[serde(default = "default_dm_opacity")]
+    pub opacity: f64,
+    /// 可占用的屏幕高度比例 0-1
+    #[serde(default = "default_dm_density")]
+    pub density: f64,
+    #[serde(default = "default_dm_scroll_secs")]
+    pub scroll_secs: f64,
+    #[serde(default = "default_dm_fixed_secs")]
+    pub fixed_secs: f64,
+    /// 屏蔽词，"/.../" 为正则
+    #[serde(default)]
+    pub filter: Vec<String>,
+}
+
+fn default_dm_font_size() -> u32 {
+    50
+}
+fn default_dm_font_name() -> String {
+    "Microsoft YaHei".to_string()
+}
+fn default_dm_opacity() -> f64 {
+    0.8
+}
+fn default_dm_density() -> f64 {
+    1.0
+}
+fn default_dm_scroll_secs() -> f64 {
+    8.0
+}
+fn default_dm_fixed_secs() -> f64 {
+    4.0
+}
+
+impl DanmakuConfig {
+    /// 字号为 0、时长非正时退回默认值，比例截断到 0-1
+    fn normalize(&mut self) {
+        let default = Self::default();
+        if self.font_size == 0 {
+            log::warn!(
+                "[danmaku] font_size 应至少为 1，使用默认值 {}",
+                default.font_size
+            );
+            self.font_size = default.font_size;
+        }
+        self.opacity = unit_or(self.opacity, default.opacity);
+        self.density = unit_or(self.density, default.density);
+        if !(self.scroll_secs.is_finite() && self.scroll_secs > 0.0) {
+            self.scroll_secs = default.scroll_secs;
+        }
+        if !(self.fixed_secs.is_finite() && self.fixed_secs > 0.0) {
+            self.fixed_secs = default.fixed_secs;
+        }
+    }
+}
+
+/// 截断到 0-1；NaN 时取默认值
+fn unit_or(v: f64, default: f64) -> f64 {
+    if v.is_nan() {
+        default
+    } else {
+        v.clamp(0.0, 1.0)
+    }
+}
+
+impl Default for DanmakuConfig {
+    fn default() -> Self {
+        Self {
+            font_size: default_dm_font_size(),
+            font_name: default_dm_font_name(),
+            opacity: default_dm_opacity(),
+            density: default_dm_density(),
+            scroll_secs: default_dm_scroll_secs(),
+            fixed_secs: default_dm_fixed_secs(),
+            filter: Vec::new(),
+        }
+    }
+}
+
+/// 播放地址的主机过滤与改写；过滤后为空时保留原地址
+#[derive(Debug, Deserialize, Default, Clone)]
+pub struct CdnConfig {
+    /// 丢弃匹配这些规则的主机
+    #[serde(default)]
+    pub deny: Vec<String>,
+    /// 非空时仅保留匹配这些规则的主机
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// 非空时将主机替换为该值
+    #[serde(default)]
+    pub replace_host: String,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct CookiesConfig {
     pub path: String,
@@ -89,6 +351,16 @@ pub struct Config {
     #[serde(default)]
     pub storage: StorageConfig,
     #[serde(default)]
+    pub hls: HlsConfig,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    #[serde(default)]
+    pub season: SeasonConfig,
+    #[serde(default)]
+    pub danmaku: DanmakuConfig,
+    #[serde(default)]
+    pub cdn: CdnConfig,
+    #[serde(default)]
     pub cookies: CookiesConfig,
 }
 
@@ -123,6 +395,13 @@ pub static CONFIG: Lazy<Config> = Lazy::new(|| {
                 if cfg.storage.stream_ext.is_empty() {
                     cfg.storage.stream_ext = StorageConfig::default().stream_ext;
                 }
+                if cfg.hls.segment_format.is_empty() {
+                    cfg.hls.segment_format = HlsConfig::default().segment_format;
+                }
+                if cfg.danmaku.font_name.is_empty() {
+                    cfg.danmaku.font_name = DanmakuConfig::default().font_name;
+                }
+                cfg.danmaku.normalize();
                 if cfg.cookies.path.is_empty() {
                     cfg.cookies.path = CookiesConfig::default().path;
                 }
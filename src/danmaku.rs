@@ -0,0 +1,659 @@
+//! 弹幕：按 cid 获取 XML 弹幕（list.so），可输出 JSON、XML，或排布为滚动 / 顶部 / 底部
+//! 三类轨道的 ASS 字幕供 mpv、Animeko 等播放器直接加载。
+
+use actix_web::{HttpRequest, HttpResponse, Responder, routes, web};
+use anyhow::{Result, anyhow};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::Read;
+
+use crate::{config, hls};
+
+/// ASS 画布尺寸
+const PLAY_RES_X: u32 = 1920;
+const PLAY_RES_Y: u32 = 1080;
+/// 字号上限：一条轨道占满画布高度
+const MAX_FONT_SIZE: u32 = PLAY_RES_Y;
+
+/// list.so 返回未带 zlib 头的 deflate 数据，而 reqwest 按 zlib 解码会失败，这里关闭自动解压自行处理
+static DM_CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .no_deflate()
+        .user_agent("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36")
+        .build()
+        .unwrap_or_default()
+});
+
+static D_TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r#"<d p="([^"]*)">([^<]*)</d>"#).unwrap());
+static ENTITY: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"&(#x[0-9a-fA-F]+|#[0-9]+|amp|lt|gt|quot|apos);").unwrap());
+
+/// 一条弹幕，对应 XML 中 `<d p="time,mode,size,color,timestamp,pool,uid_hash,id,...">`
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Danmaku {
+    /// 出现时间（秒）
+    pub time: f64,
+    /// 1-3 滚动，4 底部，5 顶部，6 逆向，7 高级，8 代码
+    pub mode: u8,
+    /// 字号：18 小、25 标准、36 大
+    pub size: u32,
+    /// 十进制 RGB
+    pub color: u32,
+    /// 发送时间（Unix 秒）
+    pub timestamp: u64,
+    pub pool: u8,
+    pub uid_hash: String,
+    pub id: String,
+    pub text: String,
+}
+
+/// 弹幕类型（决定轨道）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Lane {
+    Scroll,
+    Top,
+    Bottom,
+}
+
+impl Danmaku {
+    fn lane(&self) -> Option<Lane> {
+        match self.mode {
+            1..=3 | 6 => Some(Lane::Scroll),
+            5 => Some(Lane::Top),
+            4 => Some(Lane::Bottom),
+            // 高级 / 代码弹幕无法用普通字幕表达
+            _ => None,
+        }
+    }
+}
+
+fn unescape_xml(s: &str) -> String {
+    ENTITY
+        .replace_all(s, |caps: &regex::Captures| {
+            let e = &caps[1];
+            match e {
+                "amp" => "&".to_string(),
+                "lt" => "<".to_string(),
+                "gt" => ">".to_string(),
+                "quot" => "\"".to_string(),
+                "apos" => "'".to_string(),
+                _ => {
+                    let code = match e.strip_prefix("#x") {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                        None => e[1..].parse().ok(),
+                    };
+                    code.and_then(char::from_u32)
+                        .map(String::from)
+                        .unwrap_or_else(|| caps[0].to_string())
+                }
+            }
+        })
+        .into_owned()
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// 解析 list.so 返回的 XML，按出现时间排序；格式不符的条目跳过
+pub fn parse_xml(xml: &str) -> Vec<Danmaku> {
+    let mut list: Vec<Danmaku> = D_TAG
+        .captures_iter(xml)
+        .filter_map(|caps| {
+            let p: Vec<&str> = caps[1].split(',').collect();
+            if p.len() < 8 {
+                return None;
+            }
+            Some(Danmaku {
+                time: p[0].parse().ok()?,
+                mode: p[1].parse().ok()?,
+                size: p[2].parse().unwrap_or(25),
+                color: p[3].parse().unwrap_or(0xFFFFFF),
+                timestamp: p[4].parse().unwrap_or(0),
+                pool: p[5].parse().unwrap_or(0),
+                uid_hash: p[6].to_string(),
+                id: p[7].to_string(),
+                text: unescape_xml(&caps[2]),
+            })
+        })
+        .collect();
+    list.sort_by(|a, b| a.time.total_cmp(&b.time));
+    list
+}
+
+/// 重新生成 XML（已应用关键词过滤）
+pub fn render_xml(cid: u64, list: &[Danmaku]) -> String {
+    let mut out = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?><i><chatserver>chat.bilibili.com</chatserver><chatid>{}</chatid>",
+        cid
+    );
+    for d in list {
+        out.push_str(&format!(
+            "<d p=\"{},{},{},{},{},{},{},{}\">{}</d>",
+            d.time,
+            d.mode,
+            d.size,
+            d.color,
+            d.timestamp,
+            d.pool,
+            d.uid_hash,
+            d.id,
+            escape_xml(&d.text)
+        ));
+    }
+    out.push_str("</i>");
+    out
+}
+
+/// 屏蔽规则：普通关键词按包含匹配；`/.../` 形式按正则匹配
+pub struct Filter {
+    keywords: Vec<String>,
+    patterns: Vec<Regex>,
+}
+
+impl Filter {
+    pub fn new<'a>(rules: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut keywords = Vec::new();
+        let mut patterns = Vec::new();
+        for rule in rules.into_iter().map(str::trim).filter(|r| !r.is_empty()) {
+            if let Some(re) = rule
+                .strip_prefix('/')
+                .and_then(|r| r.strip_suffix('/'))
+                .filter(|r| !r.is_empty())
+            {
+                match Regex::new(re) {
+                    Ok(re) => patterns.push(re),
+                    Err(e) => log::warn!("弹幕屏蔽正则无效 {}: {e}", rule),
+                }
+            } else {
+                keywords.push(rule.to_string());
+            }
+        }
+        Self { keywords, patterns }
+    }
+
+    pub fn blocks(&self, text: &str) -> bool {
+        self.keywords.iter().any(|k| text.contains(k.as_str()))
+            || self.patterns.iter().any(|re| re.is_match(text))
+    }
+}
+
+/// ASS 排布参数
+#[derive(Debug, Clone)]
+pub struct AssOptions {
+    /// 标准字号（25 号弹幕）在 1080p 画布上的像素大小
+    pub font_size: u32,
+    pub font_name: String,
+    /// 不透明度 0-1
+    pub opacity: f64,
+    /// 弹幕可占用的屏幕高度比例 0-1，放不下的弹幕直接丢弃
+    pub density: f64,
+    /// 滚动弹幕横穿屏幕的时间（秒）
+    pub scroll_secs: f64,
+    /// 顶部 / 底部弹幕停留时间（秒）
+    pub fixed_secs: f64,
+}
+
+impl AssOptions {
+    fn from_config() -> Self {
+        let cfg = &config::get().danmaku;
+        Self {
+            font_size: cfg.font_size,
+            font_name: cfg.font_name.clone(),
+            opacity: cfg.opacity,
+            density: cfg.density,
+            scroll_secs: cfg.scroll_secs,
+            fixed_secs: cfg.fixed_secs,
+        }
+    }
+
+    /// 用请求参数 `font_size`、`opacity`、`density` 覆盖配置，取值超出范围时报错
+    fn apply_query(&mut self, q: &HashMap<String, String>) -> Result<(), String> {
+        if let Some(v) = q.get("font_size") {
+            self.font_size = v
+                .parse()
+                .ok()
+                .filter(|n| (1..=MAX_FONT_SIZE).contains(n))
+                .ok_or(format!("font_size 应在 1-{} 之间", MAX_FONT_SIZE))?;
+        }
+        let unit = |key: &str| match q.get(key) {
+            None => Ok(None),
+            Some(v) => v
+                .parse::<f64>()
+                .ok()
+                .filter(|n| (0.0..=1.0).contains(n))
+                .map(Some)
+                .ok_or(format!("{} 应在 0-1 之间", key)),
+        };
+        if let Some(v) = unit("opacity")? {
+            self.opacity = v;
+        }
+        if let Some(v) = unit("density")? {
+            self.density = v;
+        }
+        Ok(())
+    }
+}
+
+fn ass_time(secs: f64) -> String {
+    let cs = (secs.max(0.0) * 100.0).round() as u64;
+    format!(
+        "{}:{:02}:{:02}.{:02}",
+        cs / 360_000,
+        cs / 6000 % 60,
+        cs / 100 % 60,
+        cs % 100
+    )
+}
+
+/// 十进制 RGB 转 ASS 的 BGR 颜色
+fn ass_color(rgb: u32) -> String {
+    format!(
+        "&H{:02X}{:02X}{:02X}&",
+        rgb & 0xFF,
+        (rgb >> 8) & 0xFF,
+        (rgb >> 16) & 0xFF
+    )
+}
+
+fn ass_text(s: &str) -> String {
+    s.replace('\\', "＼")
+        .replace('{', "｛")
+        .replace('}', "｝")
+        .replace("\r\n", "\\N")
+        .replace('\n', "\\N")
+}
+
+/// 估算文本宽度：全角字符按一个字号，半角按半个字号
+fn text_width(text: &str, font: f64) -> f64 {
+    text.chars()
+        .map(|c| if c.is_ascii() { font * 0.5 } else { font })
+        .sum()
+}
+
+/// 生成 ASS 字幕。每类弹幕按字号高度划分轨道，同一轨道内的弹幕互不重叠：
+/// 滚动弹幕要求前一条已完全进入屏幕且后一条追不上它；顶部 / 底部弹幕要求前一条已消失。
+pub fn render_ass(list: &[Danmaku], opts: &AssOptions, title: &str) -> String {
+    let alpha = ((1.0 - opts.opacity.clamp(0.0, 1.0)) * 255.0).round() as u8;
+    let mut out = format!(
+        "[Script Info]\nTitle: {}\nScriptType: v4.00+\nWrapStyle: 2\nScaledBorderAndShadow: yes\nPlayResX: {}\nPlayResY: {}\n\n",
+        title, PLAY_RES_X, PLAY_RES_Y
+    );
+    out.push_str("[V4+ Styles]\nFormat: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\n");
+    out.push_str(&format!(
+        "Style: Danmaku,{},{},&H{:02X}FFFFFF,&H{:02X}FFFFFF,&H{:02X}000000,&H{:02X}000000,0,0,0,0,100,100,0,0,1,1,0,7,0,0,0,1\n\n",
+        opts.font_name, opts.font_size, alpha, alpha, alpha, alpha
+    ));
+    out.push_str(
+        "[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n",
+    );
+
+    // 字号为 0 时轨道数会趋于无穷，按 1 像素计并以画布高度为上限
+    let lane_height = (f64::from(opts.font_size) * 1.15).max(1.0);
+    let usable = f64::from(PLAY_RES_Y) * opts.density.clamp(0.0, 1.0);
+    let lanes = ((usable / lane_height).floor() as usize).clamp(1, PLAY_RES_Y as usize);
+    let width = f64::from(PLAY_RES_X);
+    // 每条轨道上一条弹幕的 (出现时间, 宽度) 或消失时间
+    let mut scroll: Vec<Option<(f64, f64)>> = vec![None; lanes];
+    let mut top: Vec<f64> = vec![f64::MIN; lanes];
+    let mut bottom: Vec<f64> = vec![f64::MIN; lanes];
+    let d = opts.scroll_secs.max(1.0);
+
+    for dm in list {
+        let Some(kind) = dm.lane() else {
+            continue;
+        };
+        let font = f64::from(opts.font_size) * f64::from(dm.size.max(1)) / 25.0;
+        let w = text_width(&dm.text, font);
+        let t = dm.time;
+        let color = if dm.color == 0xFFFFFF {
+            String::new()
+        } else {
+            format!("\\c{}", ass_color(dm.color))
+        };
+        let size = if dm.size == 25 {
+            String::new()
+        } else {
+            format!("\\fs{}", font.round())
+        };
+        let (end, effect) = match kind {
+            Lane::Scroll => {
+                let free = scroll.iter().position(|prev| match prev {
+                    None => true,
+                    Some((pt, pw)) => {
+                        // 前一条的尾部已进入屏幕
+                        let entered = pt + d * pw / (width + pw) <= t;
+                        // 后一条到达左边缘时前一条已离开
+                        let no_catch = t + d * width / (width + w) >= pt + d;
+                        entered && no_catch
+                    }
+                });
+                let Some(lane) = free else {
+                    continue;
+                };
+                scroll[lane] = Some((t, w));
+                let y = lane as f64 * lane_height;
+                (
+                    t + d,
+                    format!(
+                        "\\move({},{},{},{})",
+                        width.round(),
+                        y.round(),
+                        (-w).round(),
+                        y.round()
+                    ),
+                )
+            }
+            Lane::Top | Lane::Bottom => {
+                let slots = if kind == Lane::Top {
+                    &mut top
+                } else {
+                    &mut bottom
+                };
+                let Some(lane) = slots.iter().position(|until| *until <= t) else {
+                    continue;
+                };
+                slots[lane] = t + opts.fixed_secs;
+                let (align, y) = if kind == Lane::Top {
+                    (8, lane as f64 * lane_height)
+                } else {
+                    (2, f64::from(PLAY_RES_Y) - lane as f64 * lane_height)
+                };
+                (
+                    t + opts.fixed_secs,
+                    format!("\\an{}\\pos({},{})", align, width / 2.0, y.round()),
+                )
+            }
+        };
+        out.push_str(&format!(
+            "Dialogue: 2,{},{},Danmaku,,0,0,0,,{{{}{}{}}}{}\n",
+            ass_time(t),
+            ass_time(end),
+            effect,
+            color,
+            size,
+            ass_text(&dm.text)
+        ));
+    }
+    out
+}
+
+fn inflate(bytes: &[u8]) -> String {
+    use flate2::read::{DeflateDecoder, ZlibDecoder};
+    let mut out = String::new();
+    if DeflateDecoder::new(bytes).read_to_string(&mut out).is_ok() {
+        return out;
+    }
+    out.clear();
+    if ZlibDecoder::new(bytes).read_to_string(&mut out).is_ok() {
+        return out;
+    }
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+/// 获取某一 P 的全部 XML 弹幕
+pub async fn fetch_xml(cid: u64) -> Result<String> {
+    let resp = DM_CLIENT
+        .get(format!(
+            "https://api.bilibili.com/x/v1/dm/list.so?oid={}",
+            cid
+        ))
+        .header("Referer", "https://www.bilibili.com")
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        return Err(anyhow!("获取弹幕失败: HTTP {}", resp.status()));
+    }
+    let bytes = resp.bytes().await?;
+    Ok(inflate(&bytes))
+}
+
+/// `/danmaku/{season_id}/{sort}?format=json|xml|ass`（普通视频为 `/ugc/danmaku/{bvid}/{page}`），
+/// 可用 `font_size`、`opacity`、`density`、`filter`（逗号分隔，追加到配置的屏蔽词）覆盖配置
+#[routes]
+#[get("/danmaku/{season_id}/{sort}")]
+#[get("/ugc/danmaku/{bvid}/{page}")]
+pub async fn danmaku_endpoint(
+    path: web::Path<(String, String)>,
+    q: web::Query<HashMap<String, String>>,
+    data: web::Data<crate::AppState>,
+    req: HttpRequest,
+) -> impl Responder {
+    let (season_id, sort) = path.into_inner();
+    if let Some(resp) = hls::redirect_to_ep(&data.client, &season_id, &sort, "", &req).await {
+        return resp;
+    }
+    let result = async {
+        let cid = hls::resolve_episode(&data.client, &season_id, &sort)
+            .await?
+            .cid;
+        let xml = fetch_xml(cid).await?;
+        Ok::<_, anyhow::Error>((cid, parse_xml(&xml)))
+    }
+    .await;
+    let (cid, mut list) = match result {
+        Ok(r) => r,
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(crate::ApiResult {
+                code: 500,
+                success: false,
+                message: format!("获取弹幕失败: {e}"),
+                data: (),
+            });
+        }
+    };
+
+    let cfg = &config::get().danmaku;
+    let extra = q.get("filter").map(String::as_str).unwrap_or("");
+    let filter = Filter::new(
+        cfg.filter
+            .iter()
+            .map(String::as_str)
+            .chain(extra.split(',')),
+    );
+    list.retain(|d| !filter.blocks(&d.text));
+
+    match q.get("format").map(String::as_str).unwrap_or("json") {
+        "xml" => HttpResponse::Ok()
+            .insert_header(("Content-Type", "application/xml; charset=utf-8"))
+            .body(render_xml(cid, &list)),
+        "ass" => {
+            let mut opts = AssOptions::from_config();
+            if let Err(msg) = opts.apply_query(&q) {
+                return HttpResponse::BadRequest().json(crate::ApiResult {
+                    code: 400,
+                    success: false,
+                    message: msg,
+                    data: (),
+                });
+            }
+            let title = format!("{}-{}", season_id, sort);
+            HttpResponse::Ok()
+                .insert_header(("Content-Type", "text/x-ssa; charset=utf-8"))
+                .insert_header((
+                    "Content-Disposition",
+                    format!("inline; filename=\"{}.ass\"", title),
+                ))
+                .body(render_ass(&list, &opts, &title))
+        }
+        _ => HttpResponse::Ok().json(crate::ApiResult {
+            code: 0,
+            success: true,
+            message: String::new(),
+            data: list,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = include_str!("../tests/fixtures/danmaku.xml");
+
+    fn opts() -> AssOptions {
+        AssOptions {
+            font_size: 50,
+            font_name: "sans-serif".to_string(),
+            opacity: 0.8,
+            density: 1.0,
+            scroll_secs: 8.0,
+            fixed_secs: 4.0,
+        }
+    }
+
+    fn dm(time: f64, mode: u8, text: &str) -> Danmaku {
+        Danmaku {
+            time,
+            mode,
+            size: 25,
+            color: 0xFFFFFF,
+            timestamp: 0,
+            pool: 0,
+            uid_hash: String::new(),
+            id: String::new(),
+            text: text.to_string(),
+        }
+    }
+
+    fn dialogues(ass: &str) -> Vec<&str> {
+        ass.lines().filter(|l| l.starts_with("Dialogue:")).collect()
+    }
+
+    #[test]
+    fn parses_fixture_sorted_by_time() {
+        let list = parse_xml(FIXTURE);
+        assert_eq!(list.len(), 8);
+        assert!(list.windows(2).all(|w| w[0].time <= w[1].time));
+        let first = &list[0];
+        assert_eq!(first.time, 1.5);
+        assert_eq!(first.mode, 1);
+        assert_eq!(first.size, 25);
+        assert_eq!(first.color, 16777215);
+        assert_eq!(first.timestamp, 1700000000);
+        assert_eq!(first.uid_hash, "a1b2c3d4");
+        assert_eq!(first.id, "1000000000000000001");
+        assert_eq!(first.text, "前排");
+    }
+
+    #[test]
+    fn skips_malformed_entries() {
+        let list = parse_xml(FIXTURE);
+        assert!(list.iter().all(|d| d.text != "坏数据"));
+    }
+
+    #[test]
+    fn unescapes_entities() {
+        let list = parse_xml(FIXTURE);
+        assert!(list.iter().any(|d| d.text == "<3 & \"好\" 'A'"));
+    }
+
+    #[test]
+    fn xml_round_trip() {
+        let list = parse_xml(FIXTURE);
+        assert_eq!(parse_xml(&render_xml(1, &list)), list);
+    }
+
+    #[test]
+    fn filter_keywords_and_regex() {
+        let filter = Filter::new(["剧透", "/^2{3,}$/", ""]);
+        assert!(filter.blocks("前方剧透预警"));
+        assert!(filter.blocks("22222"));
+        assert!(!filter.blocks("22"));
+        assert!(!filter.blocks("前排"));
+        let list: Vec<Danmaku> = parse_xml(FIXTURE)
+            .into_iter()
+            .filter(|d| !filter.blocks(&d.text))
+            .collect();
+        assert_eq!(list.len(), 6);
+    }
+
+    #[test]
+    fn ass_lanes_by_mode() {
+        let ass = render_ass(&parse_xml(FIXTURE), &opts(), "test");
+        let lines = dialogues(&ass);
+        // 高级弹幕（mode 7）被跳过
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines.iter().filter(|l| l.contains("\\move(")).count(), 5);
+        assert_eq!(lines.iter().filter(|l| l.contains("\\an8")).count(), 1);
+        assert_eq!(lines.iter().filter(|l| l.contains("\\an2")).count(), 1);
+    }
+
+    #[test]
+    fn ass_style_colors_and_times() {
+        let ass = render_ass(&parse_xml(FIXTURE), &opts(), "test");
+        // 0.8 不透明度 => alpha 0x33
+        assert!(ass.contains("Style: Danmaku,sans-serif,50,&H33FFFFFF"));
+        // 红色 0xFF0000 => BGR 0000FF
+        assert!(ass.contains("\\c&H0000FF&"));
+        assert!(ass.contains("Dialogue: 2,0:00:01.50,0:00:09.50,"));
+        assert_eq!(ass_time(3725.456), "1:02:05.46");
+    }
+
+    #[test]
+    fn ass_escapes_override_braces() {
+        let ass = render_ass(&[dm(0.0, 1, "{\\b1}")], &opts(), "t");
+        assert!(dialogues(&ass)[0].ends_with("｛＼b1｝"));
+    }
+
+    #[test]
+    fn simultaneous_scrolls_use_separate_lanes() {
+        let list: Vec<Danmaku> = (0..3).map(|i| dm(1.0, 1, &format!("弹幕{}", i))).collect();
+        let ass = render_ass(&list, &opts(), "t");
+        let ys: Vec<String> = dialogues(&ass)
+            .iter()
+            .map(|l| l.split(',').nth(10).unwrap().to_string())
+            .collect();
+        assert_eq!(ys.len(), 3);
+        assert!(ys[0] != ys[1] && ys[1] != ys[2] && ys[0] != ys[2]);
+    }
+
+    #[test]
+    fn degenerate_font_size_keeps_lanes_bounded() {
+        let list: Vec<Danmaku> = (0..2000).map(|_| dm(1.0, 5, "顶部")).collect();
+        for font_size in [0, 1, u32::MAX] {
+            let o = AssOptions {
+                font_size,
+                ..opts()
+            };
+            let n = dialogues(&render_ass(&list, &o, "t")).len();
+            assert!((1..=PLAY_RES_Y as usize).contains(&n), "{font_size}: {n}");
+        }
+    }
+
+    #[test]
+    fn query_overrides_are_validated() {
+        let query = |pairs: &[(&str, &str)]| {
+            let q: HashMap<String, String> = pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect();
+            let mut o = opts();
+            o.apply_query(&q).map(|_| o)
+        };
+        let o = query(&[("font_size", "36"), ("opacity", "0.5")]).unwrap();
+        assert_eq!((o.font_size, o.opacity, o.density), (36, 0.5, 1.0));
+        assert!(query(&[("font_size", "0")]).is_err());
+        assert!(query(&[("font_size", "99999")]).is_err());
+        assert!(query(&[("opacity", "1.5")]).is_err());
+        assert!(query(&[("density", "NaN")]).is_err());
+        assert!(query(&[("density", "abc")]).is_err());
+    }
+
+    #[test]
+    fn density_drops_overflow() {
+        let mut o = opts();
+        // 1080 * 0.1 / (50 * 1.15) => 1 条轨道
+        o.density = 0.1;
+        let list: Vec<Danmaku> = (0..5).map(|_| dm(1.0, 5, "顶部")).collect();
+        assert_eq!(dialogues(&render_ass(&list, &o, "t")).len(), 1);
+        // 前一条消失后同一轨道可再次使用
+        let list = vec![dm(1.0, 5, "a"), dm(5.0, 5, "b")];
+        assert_eq!(dialogues(&render_ass(&list, &o, "t")).len(), 2);
+    }
+}
@@ -0,0 +1,171 @@
+//! 配音版本：B 站常把中配、粤配作为独立的 season 发布，并在番剧接口的 `seasons[]` 中互相关联。
+//! 去掉配音标记后标题相同的 season 视为同一部作品的不同版本。
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use std::collections::HashSet;
+
+use crate::bili::season::{Season, SeasonRef};
+
+/// 配音标记，如 `（中配）`、`粤配版`、`【普通话】`、`国语版`。
+/// 普通话、国语、粤语、日语、原声 也常出现在正常标题中，只在括号内或作为结尾的 `…版` 时算作标记
+static DUB_MARK: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(concat!(
+        r"[（(【\[]\s*(中文配音|中配|普通话|国语|粤配|粤语|日配|日语|原声)版?\s*[）)】\]]",
+        r"|(中文配音|中配|粤配|日配)版?",
+        r"|(普通话|国语|粤语|日语|原声)版\s*$",
+    ))
+    .unwrap()
+});
+
+/// 同一作品的另一配音版本
+#[derive(Debug, Clone)]
+pub struct Sibling {
+    pub season_id: i64,
+    /// 中配 / 粤配 / 日配 / 原版
+    pub label: &'static str,
+}
+
+/// 标题中的配音标记，归一为 中配 / 粤配 / 日配 / 原版；没有标记时为 None
+pub fn dub_label(title: &str) -> Option<&'static str> {
+    let caps = DUB_MARK.captures(title)?;
+    let mark = caps.iter().skip(1).flatten().next()?.as_str();
+    Some(match mark {
+        "中文配音" | "中配" | "普通话" | "国语" => "中配",
+        "粤配" | "粤语" => "粤配",
+        "原声" => "原版",
+        _ => "日配",
+    })
+}
+
+/// 标题带有原版以外的配音标记
+pub fn is_dub(title: &str) -> bool {
+    dub_label(title).is_some_and(|label| label != "原版")
+}
+
+/// 去掉配音标记与空白后的标题，用于判断两个 season 是否为同一作品
+pub fn base_title(title: &str) -> String {
+    DUB_MARK
+        .replace_all(title, "")
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect()
+}
+
+fn season_name(s: &SeasonRef) -> &str {
+    if s.title.is_empty() {
+        &s.season_title
+    } else {
+        &s.title
+    }
+}
+
+/// 番剧接口 result 中与当前 season 仅配音不同的其它 season
+pub fn siblings(season: &Season) -> Vec<Sibling> {
+    let season_id = season.season_id;
+    // 当前 season 的名称优先取 seasons[] 中的同一条，与兄弟条目口径一致
+    let own = season
+        .seasons
+        .iter()
+        .find(|s| s.season_id == season_id)
+        .map(season_name)
+        .unwrap_or_else(|| season.display_title());
+    let own_base = base_title(own);
+    // 未标记与标记为原声的都是原版
+    let own_label = dub_label(own).unwrap_or("原版");
+    if own_base.is_empty() {
+        return Vec::new();
+    }
+    season
+        .seasons
+        .iter()
+        .filter(|s| s.season_id != season_id)
+        .filter_map(|s| {
+            let name = season_name(s);
+            let label = dub_label(name).unwrap_or("原版");
+            (label != own_label && base_title(name) == own_base).then_some(Sibling {
+                season_id: s.season_id,
+                label,
+            })
+        })
+        .collect()
+}
+
+/// seasons[] 中另有同名原版的配音版，如 `第二季（中配）`、配音的剧场版
+pub fn dub_editions(seasons: &[SeasonRef]) -> HashSet<i64> {
+    let originals: HashSet<String> = seasons
+        .iter()
+        .map(season_name)
+        .filter(|name| !is_dub(name))
+        .map(base_title)
+        .collect();
+    seasons
+        .iter()
+        .filter(|s| {
+            let name = season_name(s);
+            is_dub(name) && originals.contains(&base_title(name))
+        })
+        .map(|s| s.season_id)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_dub_marks() {
+        assert_eq!(dub_label("葬送的芙莉莲（中配）"), Some("中配"));
+        assert_eq!(dub_label("间谍过家家 中配版"), Some("中配"));
+        assert_eq!(dub_label("【普通话】名侦探柯南"), Some("中配"));
+        assert_eq!(dub_label("蜡笔小新 国语版"), Some("中配"));
+        assert_eq!(dub_label("名侦探柯南 粤配"), Some("粤配"));
+        assert_eq!(dub_label("哆啦A梦（粤语版）"), Some("粤配"));
+        assert_eq!(dub_label("进击的巨人（日配）"), Some("日配"));
+        assert_eq!(dub_label("航海王（原声版）"), Some("原版"));
+        assert_eq!(dub_label("葬送的芙莉莲"), None);
+    }
+
+    #[test]
+    fn ignores_language_words_in_plain_titles() {
+        assert_eq!(dub_label("跟着日语去旅行"), None);
+        assert_eq!(dub_label("普通话水平测试精讲"), None);
+        assert_eq!(dub_label("国语课本里的故事 第二季"), None);
+        assert_eq!(dub_label("千与千寻 原声音乐会"), None);
+        assert!(!is_dub("航海王（原声版）"));
+        assert!(is_dub("间谍过家家 中配版"));
+    }
+
+    #[test]
+    fn base_title_strips_marks_and_spaces() {
+        assert_eq!(base_title("葬送的芙莉莲（中配）"), "葬送的芙莉莲");
+        assert_eq!(base_title("间谍过家家 中配版"), "间谍过家家");
+        assert_eq!(base_title("【普通话】名侦探柯南"), "名侦探柯南");
+        assert_eq!(base_title("蜡笔小新 国语版"), "蜡笔小新");
+        assert_eq!(base_title("跟着日语去旅行"), "跟着日语去旅行");
+        assert_eq!(base_title("间谍过家家 第二季"), "间谍过家家第二季");
+    }
+
+    #[test]
+    fn siblings_of_fixture_season() {
+        let v: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/fixtures/season.json")).unwrap();
+        let season = crate::bili::season::parse(v["result"].clone()).unwrap();
+        let sibs = siblings(&season);
+        assert_eq!(sibs.len(), 1);
+        assert_eq!(sibs[0].season_id, 47040);
+        assert_eq!(sibs[0].label, "中配");
+    }
+
+    #[test]
+    fn dub_editions_of_every_season() {
+        let v: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/fixtures/season.json")).unwrap();
+        let season = crate::bili::season::parse(v["result"].clone()).unwrap();
+        let dubs = dub_editions(&season.seasons);
+        assert_eq!(dubs, HashSet::from([47040, 50002]));
+        // 没有原版同时出现时配音版照常列出
+        assert!(dub_editions(&season.seasons[3..]).is_empty());
+    }
+}
@@ -1,19 +1,453 @@
-use actix_web::{HttpRequest, HttpResponse, Responder, get, web};
+use actix_web::{HttpRequest, HttpResponse, Responder, routes, web};
 use anyhow::{Result, anyhow};
-use serde_json::Value;
-use std::fs;
+use serde::Serialize;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use tokio::process::Command;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tokio::process::{Child, Command};
 use tokio::time::{Duration, sleep};
 
-use crate::{config, cookies, playurl};
+use crate::bili::season::{Episode, SkipSpan};
+use crate::playurl::{PlayAudio, PlayVideo, PlayurlDash, PlayurlDurl};
+use crate::remux::TrackIndex;
+use crate::season;
+use crate::supervisor::{self, JobKey};
+use crate::ugc;
+use crate::{cache, cdn, config, cookies, playurl, remux, subtitle};
 
+/// 转码为 H.264 后在 master playlist 中声明的编码（High@4.1，与 run_ffmpeg_hls 参数一致）
+const TRANSCODED_AVC_CODECS: &str = "avc1.640029";
+/// 转码时的 VOD 分片时长（秒）
+const SEGMENT_SECONDS: u64 = 6;
+/// 直接复制视频时传给 FFmpeg 的 hls_time：远小于任何 GOP，使其在每个关键帧处切出小分片，
+/// 再由 merge_parts 按 playlist 的边界合并
+const KEYFRAME_SPLIT_TIME: &str = "0.1";
+/// 小分片所在目录的前缀，每次运行一个目录：`parts-{运行标识}`
+pub(crate) const PARTS_DIR_PREFIX: &str = "parts-";
+/// 小分片目录中记录本次运行起始分片的文件
+const PARTS_START_FILE: &str = "start";
+/// 小分片起点与分片边界比较时的容差（秒），吸收 EXTINF 累加的误差
+const PART_BOUNDARY_TOLERANCE: f64 = 0.05;
+/// 后台合并小分片的轮询间隔；FFmpeg 的列表超过 PART_IDLE_TIMEOUT 未更新则不再轮询
+const PART_POLL_INTERVAL: Duration = Duration::from_millis(200);
+const PART_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+/// 复制模式下 seek 点相对关键帧的偏移，避免小数误差使 FFmpeg 退到前一个关键帧
+const KEYFRAME_SEEK_EPSILON: f64 = 0.01;
+/// 请求的分片超出转码进度这么多个以上时，从该分片处重启 FFmpeg
+const SEEK_RESTART_GAP: u64 = 3;
+/// durl 分段为 FLV/MP4 封装的 AVC + AAC，变体按 AVC 记录
+const DURL_CODECID: i32 = 7;
+/// durl 分段拼接列表，位于变体目录下
+const CONCAT_FILE: &str = "input.ffconcat";
+const USER_AGENT: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
+
+/// HLS 分片格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SegmentFormat {
+    /// MPEG-TS：仅 AVC 可直接复制，其它编码转码为 H.264
+    Ts,
+    /// fMP4/CMAF（init.mp4 + .m4s）：AVC/HEVC/AV1 均直接复制
+    Fmp4,
+    /// 不经 FFmpeg，按 sidx 索引直接映射上游 fMP4 字节范围（见 remux 模块）
+    Remux,
+}
+
+impl SegmentFormat {
+    fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "ts" | "mpegts" => Some(Self::Ts),
+            "fmp4" | "cmaf" | "mp4" => Some(Self::Fmp4),
+            "remux" | "sidx" => Some(Self::Remux),
+            _ => None,
+        }
+    }
+
+    /// 请求参数 `format` 优先，否则使用配置中的默认格式
+    fn from_query(q: &HashMap<String, String>) -> Self {
+        q.get("format")
+            .and_then(|f| Self::parse(f))
+            .or_else(|| Self::parse(&config::get().hls.segment_format))
+            .unwrap_or(Self::Ts)
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Ts => "ts",
+            Self::Fmp4 => "fmp4",
+            Self::Remux => "remux",
+        }
+    }
+
+    /// durl 分段没有 sidx 索引，remux 时退回 fMP4 转封装
+    fn for_dash(self, dash: &PlayurlDash) -> Self {
+        if self == SegmentFormat::Remux && dash.durl.is_some() {
+            SegmentFormat::Fmp4
+        } else {
+            self
+        }
+    }
+
+    /// 该格式是否需要转码此类音轨（TS 无法承载 FLAC）
+    fn transcode_audio(self, audio: AudioPref) -> bool {
+        self == Self::Ts && audio == AudioPref::Flac
+    }
+
+    /// 该格式下能否直接复制此编码（7=AVC, 12=HEVC, 13=AV1）
+    fn can_copy(self, codecid: Option<i32>) -> bool {
+        match self {
+            Self::Ts => codecid == Some(7),
+            Self::Fmp4 | Self::Remux => matches!(codecid, Some(7 | 12 | 13)),
+        }
+    }
+}
+
+/// 音轨类型：普通 AAC、杜比全景声（E-AC-3）或 Hi-Res 无损（FLAC）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AudioPref {
+    Standard,
+    Dolby,
+    Flac,
+}
+
+impl AudioPref {
+    fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "standard" | "aac" | "normal" => Some(Self::Standard),
+            "dolby" | "eac3" | "atmos" => Some(Self::Dolby),
+            "flac" | "hires" | "lossless" => Some(Self::Flac),
+            _ => None,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Standard => "standard",
+            Self::Dolby => "dolby",
+            Self::Flac => "flac",
+        }
+    }
+
+    /// master playlist 中显示的音轨名
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            Self::Standard => "标准",
+            Self::Dolby => "杜比全景声",
+            Self::Flac => "Hi-Res 无损",
+        }
+    }
+
+    pub(crate) fn channels(self) -> &'static str {
+        match self {
+            Self::Dolby => "16/JOC",
+            Self::Standard | Self::Flac => "2",
+        }
+    }
+
+    /// 按音轨 id（30250 杜比、30251 无损）或编码判断类型
+    pub(crate) fn of(a: &PlayAudio) -> Self {
+        let codecs = a.codecs.as_deref().unwrap_or("").to_ascii_lowercase();
+        if a.id == 30250 || codecs.starts_with("ec-3") {
+            Self::Dolby
+        } else if a.id == 30251 || codecs.starts_with("flac") {
+            Self::Flac
+        } else {
+            Self::Standard
+        }
+    }
+}
+
+/// 解析 `codec` 参数为 codecid（7=AVC, 12=HEVC, 13=AV1）
+fn parse_codec(s: &str) -> Option<i32> {
+    match s.to_ascii_lowercase().as_str() {
+        "avc" | "h264" | "avc1" => Some(7),
+        "hevc" | "h265" | "hvc1" => Some(12),
+        "av1" | "av01" => Some(13),
+        _ => None,
+    }
+}
+
+/// 画质偏好：请求参数 `qn` / `codec` / `audio` 优先，缺省时取 `[hls]` 配置。
+/// 请求的画质或编码不存在时逐级放宽，总能选出一条轨。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Profile {
+    /// 画质上限，None 为最高
+    qn: Option<i32>,
+    codecid: Option<i32>,
+    audio: AudioPref,
+}
+
+impl Profile {
+    fn from_query(q: &HashMap<String, String>) -> Self {
+        let cfg = &config::get().hls;
+        let qn = q
+            .get("qn")
+            .and_then(|s| s.parse::<i32>().ok())
+            .unwrap_or(cfg.default_qn);
+        Self {
+            qn: (qn > 0).then_some(qn),
+            codecid: q
+                .get("codec")
+                .and_then(|s| parse_codec(s))
+                .or_else(|| parse_codec(&cfg.default_codec)),
+            audio: q
+                .get("audio")
+                .and_then(|s| AudioPref::parse(s))
+                .or_else(|| AudioPref::parse(&cfg.default_audio))
+                .unwrap_or(AudioPref::Standard),
+        }
+    }
+
+    /// 用于默认变体标记文件名，如 `q80-7-standard`、`qmax-any-dolby`
+    fn key(&self) -> String {
+        format!(
+            "q{}-{}-{}",
+            self.qn.map_or("max".to_string(), |q| q.to_string()),
+            self.codecid.map_or("any".to_string(), |c| c.to_string()),
+            self.audio.as_str()
+        )
+    }
+
+    /// 符合偏好的视频轨：先按编码筛选（无此编码则不限），再取不超过 qn 的画质（都超过则取最低画质）
+    fn videos<'a>(&self, dash: &'a PlayurlDash) -> Vec<&'a PlayVideo> {
+        let mut list: Vec<&PlayVideo> = dash.video.iter().collect();
+        if let Some(codecid) = self.codecid {
+            let matched: Vec<&PlayVideo> = list
+                .iter()
+                .copied()
+                .filter(|v| v.codecid == Some(codecid))
+                .collect();
+            if matched.is_empty() {
+                log::info!("没有 codecid={} 的视频轨，改用其它编码", codecid);
+            } else {
+                list = matched;
+            }
+        }
+        if let Some(qn) = self.qn {
+            let matched: Vec<&PlayVideo> = list.iter().copied().filter(|v| v.id <= qn).collect();
+            if matched.is_empty() {
+                let lowest = list.iter().map(|v| v.id).min().unwrap_or(0);
+                log::info!("没有不高于 qn={} 的画质，改用 qn={}", qn, lowest);
+                list.retain(|v| v.id == lowest);
+            } else {
+                list = matched;
+            }
+        }
+        list
+    }
+
+    /// 默认变体：最高画质；同画质优先可直接复制的编码，其次带宽最高
+    fn pick_video<'a>(
+        &self,
+        dash: &'a PlayurlDash,
+        format: SegmentFormat,
+    ) -> Result<&'a PlayVideo> {
+        self.videos(dash)
+            .into_iter()
+            .max_by_key(|v| (v.id, format.can_copy(v.codecid), v.bandwidth.unwrap_or(0)))
+            .ok_or_else(|| anyhow!("无视频轨"))
+    }
+}
+
+/// 变体：画质 + 编码 + 分片格式 (+ 非普通音轨)，对应缓存中的一个子目录
+/// `{qn}-{codecid}-{format}` 或 `{qn}-{codecid}-{format}-{audio}`；
+/// 纯音频 rendition（master 中的 EXT-X-MEDIA 备选音轨）为 `audio-{audio}-{format}`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Variant {
+    /// (qn, codecid)；None 为纯音频
+    video: Option<(i32, i32)>,
+    format: SegmentFormat,
+    audio: AudioPref,
+}
+
+impl Variant {
+    fn of(v: &PlayVideo, format: SegmentFormat, audio: AudioPref) -> Self {
+        Self {
+            video: Some((v.id, v.codecid.unwrap_or(0))),
+            format,
+            audio,
+        }
+    }
+
+    /// durl 只有一条混流的轨，固定为标准音轨
+    fn durl(durl: &PlayurlDurl, format: SegmentFormat) -> Self {
+        Self {
+            video: Some((durl.quality, DURL_CODECID)),
+            format,
+            audio: AudioPref::Standard,
+        }
+    }
+
+    fn audio_only(format: SegmentFormat, audio: AudioPref) -> Self {
+        Self {
+            video: None,
+            format,
+            audio,
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.split('-');
+        let first = parts.next()?;
+        let variant = if first == "audio" {
+            let audio = AudioPref::parse(parts.next()?)?;
+            Self::audio_only(SegmentFormat::parse(parts.next()?)?, audio)
+        } else {
+            let qn = first.parse().ok()?;
+            let codecid = parts.next()?.parse().ok()?;
+            let format = match parts.next() {
+                Some(f) => SegmentFormat::parse(f)?,
+                None => SegmentFormat::Ts,
+            };
+            let audio = match parts.next() {
+                Some(a) => AudioPref::parse(a)?,
+                None => AudioPref::Standard,
+            };
+            Self {
+                video: Some((qn, codecid)),
+                format,
+                audio,
+            }
+        };
+        // remux 模式没有 FFmpeg 输出目录，走 remux 模块自己的路由
+        if variant.format == SegmentFormat::Remux || parts.next().is_some() {
+            return None;
+        }
+        Some(variant)
+    }
+
+    fn key(&self) -> String {
+        let Some((qn, codecid)) = self.video else {
+            return format!("audio-{}-{}", self.audio.as_str(), self.format.as_str());
+        };
+        let base = format!("{}-{}-{}", qn, codecid, self.format.as_str());
+        match self.audio {
+            AudioPref::Standard => base,
+            other => format!("{}-{}", base, other.as_str()),
+        }
+    }
+
+    fn codecid(&self) -> Option<i32> {
+        self.video.map(|(_, codecid)| codecid)
+    }
+
+    fn matches(&self, v: &PlayVideo) -> bool {
+        self.video == Some((v.id, v.codecid.unwrap_or(0)))
+    }
+}
+
+#[routes]
+#[get("/hls/{season_id}/{sort}/master.m3u8")]
+#[get("/ugc/hls/{bvid}/{page}/master.m3u8")]
+pub async fn hls_master(
+    path: web::Path<(String, String)>,
+    q: web::Query<HashMap<String, String>>,
+    data: web::Data<crate::AppState>,
+    req: HttpRequest,
+) -> impl Responder {
+    if let Some(resp) = redirect_to_ep(&data.client, &path.0, &path.1, "master.m3u8", &req).await {
+        return resp;
+    }
+    let format = SegmentFormat::from_query(&q);
+    let profile = Profile::from_query(&q);
+    let result = async {
+        let dash = load_dash(&data.client, &path.0, &path.1).await?;
+        let format = format.for_dash(&dash);
+        let master = if format == SegmentFormat::Remux {
+            render_remux_master(&dash, profile)?
+        } else {
+            render_master_playlist(&dash, format, profile)?
+        };
+        // 字幕获取失败不影响播放
+        let tracks = subtitle::episode_subtitles(&data.client, &path.0, &path.1)
+            .await
+            .unwrap_or_else(|e| {
+                log::warn!("获取字幕列表失败 {}/{}: {e}", path.0, path.1);
+                Vec::new()
+            });
+        Ok::<_, anyhow::Error>(subtitle::attach_to_master(
+            &master,
+            &tracks,
+            &path.0,
+            &path.1,
+            format.as_str(),
+        ))
+    }
+    .await;
+    match result {
+        Ok(content) => HttpResponse::Ok()
+            .insert_header(("Content-Type", "application/vnd.apple.mpegurl"))
+            .insert_header(("Cache-Control", "no-store"))
+            .body(content),
+        Err(e) => error_response(&e),
+    }
+}
+
+/// 兼容入口：按画质偏好选择默认变体，分片地址改写为 `{variant}/xxx`
+#[routes]
 #[get("/hls/{season_id}/{sort}/index.m3u8")]
+#[get("/ugc/hls/{bvid}/{page}/index.m3u8")]
 pub async fn hls_playlist(
     path: web::Path<(String, String)>,
+    q: web::Query<HashMap<String, String>>,
+    data: web::Data<crate::AppState>,
+    req: HttpRequest,
+) -> impl Responder {
+    let (season_id, sort) = path.into_inner();
+    if let Some(resp) = redirect_to_ep(&data.client, &season_id, &sort, "index.m3u8", &req).await {
+        return resp;
+    }
+    let mut format = SegmentFormat::from_query(&q);
+    let profile = Profile::from_query(&q);
+    if format == SegmentFormat::Remux {
+        // 音视频分离的轨无法放进单个 media playlist，直接返回 master
+        match load_dash(&data.client, &season_id, &sort).await {
+            Ok(dash) if dash.durl.is_some() => format = format.for_dash(&dash),
+            Ok(dash) => {
+                return match render_remux_master(&dash, profile) {
+                    Ok(content) => HttpResponse::Ok()
+                        .insert_header(("Content-Type", "application/vnd.apple.mpegurl"))
+                        .insert_header(("Cache-Control", "no-store"))
+                        .body(content),
+                    Err(e) => error_response(&e),
+                };
+            }
+            Err(e) => return error_response(&e),
+        }
+    }
+    let variant = match default_variant(&data.client, &season_id, &sort, format, profile).await {
+        Ok(v) => v,
+        Err(e) => return error_response(&e),
+    };
+    match prepare_hls_pipeline(&data.client, &season_id, &sort, variant).await {
+        Ok(dir) => match wait_for_file(dir.join("index.m3u8"), 50, 100).await {
+            Ok(content) => HttpResponse::Ok()
+                .insert_header(("Content-Type", "application/vnd.apple.mpegurl"))
+                .insert_header(("Cache-Control", "no-store"))
+                .body(prefix_playlist_uris(&content, &variant.key())),
+            Err(e) => HttpResponse::ServiceUnavailable().body(format!("等待 playlist 超时: {e}")),
+        },
+        Err(e) => error_response(&e),
+    }
+}
+
+#[routes]
+#[get("/hls/{season_id}/{sort}/{variant}/index.m3u8")]
+#[get("/ugc/hls/{bvid}/{page}/{variant}/index.m3u8")]
+pub async fn hls_variant_playlist(
+    path: web::Path<(String, String, String)>,
     data: web::Data<crate::AppState>,
+    req: HttpRequest,
 ) -> impl Responder {
-    match prepare_hls_pipeline(&data.client, &path.0, &path.1).await {
+    let (season_id, sort, variant) = path.into_inner();
+    let file = format!("{}/index.m3u8", variant);
+    if let Some(resp) = redirect_to_ep(&data.client, &season_id, &sort, &file, &req).await {
+        return resp;
+    }
+    let Some(variant) = Variant::parse(&variant) else {
+        return HttpResponse::NotFound().body("变体不存在");
+    };
+    supervisor::touch(&job_key(&season_id, &sort, variant));
+    match prepare_hls_pipeline(&data.client, &season_id, &sort, variant).await {
         Ok(dir) => match wait_for_file(dir.join("index.m3u8"), 50, 100).await {
             Ok(content) => HttpResponse::Ok()
                 .insert_header(("Content-Type", "application/vnd.apple.mpegurl"))
@@ -21,28 +455,66 @@ pub async fn hls_playlist(
                 .body(content),
             Err(e) => HttpResponse::ServiceUnavailable().body(format!("等待 playlist 超时: {e}")),
         },
-        Err(e) => HttpResponse::InternalServerError()
-            .insert_header(("Content-Type", "application/json; charset=utf-8"))
-            .body(format!("{{\"error\":\"{}\"}}", escape_json(&e.to_string()))),
+        Err(e) => error_response(&e),
     }
 }
 
-#[get("/hls/{season_id}/{sort}/{seg}")]
+#[routes]
+#[get("/hls/{season_id}/{sort}/{variant}/{seg}")]
+#[get("/ugc/hls/{bvid}/{page}/{variant}/{seg}")]
 pub async fn hls_segment(
-    path: web::Path<(String, String, String)>,
-    _req: HttpRequest,
+    path: web::Path<(String, String, String, String)>,
+    data: web::Data<crate::AppState>,
+    req: HttpRequest,
 ) -> impl Responder {
-    let (season_id, sort, seg) = path.into_inner();
-    let cfg = config::get();
-    let dir = PathBuf::from(&cfg.api.cache_dir)
-        .join("hls")
-        .join(&season_id)
-        .join(&sort);
-    let seg_path = dir.join(&seg);
-    match wait_for_existing(&seg_path, 80, 100).await {
+    let (season_id, sort, variant_str, seg) = path.into_inner();
+    let file = format!("{}/{}", variant_str, seg);
+    if let Some(resp) = redirect_to_ep(&data.client, &season_id, &sort, &file, &req).await {
+        return resp;
+    }
+    let work_dir = episode_dir(&season_id, &sort).join(&variant_str);
+    let seg_path = work_dir.join(&seg);
+    let content_type = if seg.ends_with(".ts") {
+        "video/mp2t"
+    } else {
+        "video/mp4"
+    };
+    if let Some(variant) = Variant::parse(&variant_str) {
+        supervisor::touch(&job_key(&season_id, &sort, variant));
+        cache::mark_access(&work_dir);
+    }
+    if !seg_path.exists()
+        && let Some(variant) = Variant::parse(&variant_str)
+    {
+        let key = job_key(&season_id, &sort, variant);
+        cache::reconcile_dir(&work_dir, supervisor::is_active(&key));
+        // 未生成的分片：必要时从该分片处（重新）启动 FFmpeg；init.mp4 由任意运行中的任务产出
+        let segment = if seg == "init.mp4" {
+            supervisor::start_number(&key).unwrap_or(0)
+        } else {
+            match seg.split('.').next().and_then(|n| n.parse::<u64>().ok()) {
+                Some(n) => n,
+                None => return HttpResponse::NotFound().body("分片不存在"),
+            }
+        };
+        if let Err(e) = ensure_job(
+            &data.client,
+            &season_id,
+            &sort,
+            variant,
+            &work_dir,
+            segment,
+            None,
+        )
+        .await
+        {
+            return error_response(&e);
+        }
+    }
+    match wait_for_existing(&seg_path, 150, 100).await {
         Ok(_) => match tokio::fs::read(&seg_path).await {
             Ok(bytes) => HttpResponse::Ok()
-                .insert_header(("Content-Type", "video/mp2t"))
+                .insert_header(("Content-Type", content_type))
                 .insert_header(("Cache-Control", "public, max-age=86400"))
                 .body(bytes),
             Err(e) => HttpResponse::InternalServerError().body(format!("读取分片失败: {e}")),
@@ -51,148 +523,1041 @@ pub async fn hls_segment(
     }
 }
 
+/// 按位置（sort）寻址的请求重定向到同一路由下的 `ep/{ep_id}`（如 `/hls/ep/{ep_id}/`），
+/// 变体与分片都按 ep_id 缓存，上游插入或调整剧集后旧缓存不会被当作另一集使用。
+/// `file` 为 `{sort}` 之后的路径（可为空）；非按位置寻址时返回 None
+pub(crate) async fn redirect_to_ep(
+    client: &reqwest::Client,
+    season_id: &str,
+    sort: &str,
+    file: &str,
+    req: &HttpRequest,
+) -> Option<HttpResponse> {
+    let Ok(EpisodeRef::Season { season_id, sort }) = EpisodeRef::parse(season_id, sort) else {
+        return None;
+    };
+    let ep_id = fetch_episode(client, season_id, sort)
+        .await
+        .and_then(|ep| ep.ep_id().ok_or_else(|| anyhow!("ep_id missing")));
+    Some(match ep_id {
+        Ok(ep_id) => {
+            // 相对地址，部署在反向代理的子路径下也能正确跳转：先回到 `{season_id}` 所在的目录
+            let up = if file.is_empty() {
+                1
+            } else {
+                2 + file.matches('/').count()
+            };
+            let mut location = format!("{}ep/{}", "../".repeat(up), ep_id);
+            if !file.is_empty() {
+                location.push('/');
+                location.push_str(file);
+            }
+            if !req.query_string().is_empty() {
+                location.push('?');
+                location.push_str(req.query_string());
+            }
+            HttpResponse::Found()
+                .insert_header(("Location", location))
+                .insert_header(("Cache-Control", "no-store"))
+                .finish()
+        }
+        Err(e) => error_response(&e),
+    })
+}
+
 fn escape_json(s: &str) -> String {
     s.replace('"', "\\\"")
 }
 
+fn error_response(e: &anyhow::Error) -> HttpResponse {
+    HttpResponse::InternalServerError()
+        .insert_header(("Content-Type", "application/json; charset=utf-8"))
+        .body(format!("{{\"error\":\"{}\"}}", escape_json(&e.to_string())))
+}
+
+/// 单集缓存目录：cache_dir/hls/{season_id}/{sort}（按 ep_id 寻址时为 ep/{ep_id}，普通视频为 {bvid}/{page}），
+/// 每个变体位于其下的子目录
+fn episode_dir(season_id: &str, sort: &str) -> PathBuf {
+    let cfg = config::get();
+    PathBuf::from(&cfg.api.cache_dir)
+        .join("hls")
+        .join(season_id)
+        .join(sort)
+}
+
+/// 路由中 `{season_id}/{sort}` 所指的剧集
+enum EpisodeRef<'a> {
+    /// `/hls/{season_id}/{sort}`：番剧第 sort 集（从 1 开始），上游插入或调整剧集后会指向别的内容
+    Season { season_id: i64, sort: usize },
+    /// `/hls/ep/{ep_id}`：按 ep_id 稳定寻址
+    Ep(u64),
+    /// `/ugc/hls/{bvid}/{page}`：普通视频的第 page P
+    Ugc { bvid: &'a str, page: usize },
+}
+
+impl<'a> EpisodeRef<'a> {
+    fn parse(season_id: &'a str, sort: &str) -> Result<Self> {
+        if season_id == "ep" {
+            return Ok(Self::Ep(
+                sort.parse().map_err(|_| anyhow!("ep_id 应为数字"))?,
+            ));
+        }
+        if ugc::is_bvid(season_id) {
+            return Ok(Self::Ugc {
+                bvid: season_id,
+                page: sort.parse()?,
+            });
+        }
+        Ok(Self::Season {
+            season_id: season_id.parse()?,
+            sort: sort.parse()?,
+        })
+    }
+}
+
+/// 解析后的剧集标识
+pub(crate) struct EpisodeInfo {
+    /// 番剧的 (season_id, ep_id)；普通视频为 None
+    pgc: Option<(i64, u64)>,
+    pub aid: u64,
+    pub cid: u64,
+    skip: EpisodeSkip,
+}
+
+impl EpisodeInfo {
+    fn from_pgc(season_id: i64, ep: &Episode) -> Result<Self> {
+        let ep_id = ep.ep_id().ok_or_else(|| anyhow::anyhow!("ep_id missing"))?;
+        Ok(Self {
+            pgc: Some((season_id, ep_id)),
+            aid: ep.aid,
+            cid: ep.cid,
+            skip: EpisodeSkip::from_episode(ep),
+        })
+    }
+}
+
+/// 解析番剧（season_id + sort 或 ep + ep_id）或普通视频（bvid + page）
+pub(crate) async fn resolve_episode(
+    client: &reqwest::Client,
+    season_id: &str,
+    sort: &str,
+) -> Result<EpisodeInfo> {
+    match EpisodeRef::parse(season_id, sort)? {
+        EpisodeRef::Season { season_id, sort } => {
+            let ep = fetch_episode(client, season_id, sort).await?;
+            EpisodeInfo::from_pgc(season_id, &ep)
+        }
+        EpisodeRef::Ep(ep_id) => {
+            let (season_id, ep) = fetch_episode_by_id(client, ep_id).await?;
+            EpisodeInfo::from_pgc(season_id, &ep)
+        }
+        EpisodeRef::Ugc { bvid, page } => {
+            let (aid, cid) = ugc::page_ids(client, bvid, page).await?;
+            Ok(EpisodeInfo {
+                pgc: None,
+                aid,
+                cid,
+                skip: EpisodeSkip::default(),
+            })
+        }
+    }
+}
+
+pub(crate) async fn load_dash(
+    client: &reqwest::Client,
+    season_id: &str,
+    sort: &str,
+) -> Result<PlayurlDash> {
+    let info = resolve_episode(client, season_id, sort).await?;
+    fetch_dash(client, &info).await
+}
+
+/// 按已解析的剧集获取播放地址
+async fn fetch_dash(client: &reqwest::Client, info: &EpisodeInfo) -> Result<PlayurlDash> {
+    match info.pgc {
+        Some((season_id, ep_id)) => {
+            playurl::fetch_dash_pgc(client, ep_id, season_id as u64, true).await
+        }
+        None => playurl::fetch_dash_ugc(client, info.aid, info.cid, true).await,
+    }
+}
+
+/// 选择指定类型中带宽最高的音轨；没有该类型时退回普通音轨，再退回任意音轨
+fn select_audio(dash: &PlayurlDash, pref: AudioPref) -> Result<&PlayAudio> {
+    let best_of = |kind: Option<AudioPref>| {
+        dash.audio
+            .iter()
+            .filter(|a| kind.is_none_or(|k| AudioPref::of(a) == k))
+            .max_by_key(|a| a.bandwidth.unwrap_or(0))
+    };
+    best_of(Some(pref))
+        .or_else(|| best_of(Some(AudioPref::Standard)))
+        .or_else(|| best_of(None))
+        .ok_or_else(|| anyhow!("无音频轨"))
+}
+
+/// 每种音轨类型中带宽最高的一条，按 标准、杜比、无损 排列，作为 EXT-X-MEDIA 备选音轨
+pub(crate) fn audio_renditions(dash: &PlayurlDash) -> Vec<&PlayAudio> {
+    [AudioPref::Standard, AudioPref::Dolby, AudioPref::Flac]
+        .into_iter()
+        .filter_map(|kind| {
+            dash.audio
+                .iter()
+                .filter(|a| AudioPref::of(a) == kind)
+                .max_by_key(|a| a.bandwidth.unwrap_or(0))
+        })
+        .collect()
+}
+
+/// 音轨在该分片格式下输出后的 CODECS 值
+fn audio_codecs(audio: &PlayAudio, format: SegmentFormat) -> String {
+    let kind = AudioPref::of(audio);
+    if format.transcode_audio(kind) {
+        return "mp4a.40.2".to_string();
+    }
+    audio.codecs.clone().unwrap_or_else(|| {
+        match kind {
+            AudioPref::Standard => "mp4a.40.2",
+            AudioPref::Dolby => "ec-3",
+            AudioPref::Flac => "fLaC",
+        }
+        .to_string()
+    })
+}
+
+/// 生成 master playlist：符合画质偏好的每条 DASH 视频轨对应一个变体。
+/// 变体内混流所选音轨；有多种音轨时其余类型以纯音频 rendition 列入 AUDIO 组，切换音轨无需重启视频。
+/// TS 模式下非 AVC 轨会被转码为 H.264，若同画质已有 AVC 轨则跳过，避免出现重复变体；
+/// fMP4 模式下所有轨均直接复制，按原始编码声明。
+fn render_master_playlist(
+    dash: &PlayurlDash,
+    format: SegmentFormat,
+    profile: Profile,
+) -> Result<String> {
+    if let Some(durl) = &dash.durl {
+        return Ok(render_durl_master(durl, format));
+    }
+    let candidates = profile.videos(dash);
+    if candidates.is_empty() {
+        return Err(anyhow!("无视频轨"));
+    }
+    let audio = select_audio(dash, profile.audio)?;
+    let audio_kind = AudioPref::of(audio);
+    let muxed_codecs = audio_codecs(audio, format);
+    let renditions = audio_renditions(dash);
+    let mut videos = candidates.clone();
+    videos.sort_by_key(|v| std::cmp::Reverse(v.bandwidth.unwrap_or(0)));
+
+    let version = match format {
+        SegmentFormat::Ts => 3,
+        SegmentFormat::Fmp4 | SegmentFormat::Remux => 7,
+    };
+    let mut out = format!(
+        "#EXTM3U\n#EXT-X-VERSION:{}\n#EXT-X-INDEPENDENT-SEGMENTS\n",
+        version
+    );
+    let audio_group = renditions.len() > 1;
+    if audio_group {
+        for a in &renditions {
+            let kind = AudioPref::of(a);
+            // 与变体混流的音轨不带 URI，其余指向纯音频 rendition
+            let uri = if kind == audio_kind {
+                String::new()
+            } else {
+                format!(
+                    ",URI=\"{}/index.m3u8\"",
+                    Variant::audio_only(format, kind).key()
+                )
+            };
+            out.push_str(&format!(
+                "#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"{}\",DEFAULT={},AUTOSELECT=YES,CHANNELS=\"{}\"{}\n",
+                kind.label(),
+                if kind == audio_kind { "YES" } else { "NO" },
+                kind.channels(),
+                uri
+            ));
+        }
+    }
+    for v in videos {
+        let copy = format.can_copy(v.codecid);
+        if !copy
+            && candidates
+                .iter()
+                .any(|o| o.id == v.id && format.can_copy(o.codecid))
+        {
+            continue;
+        }
+        let video_codecs = if copy {
+            v.codecs.as_deref().unwrap_or(TRANSCODED_AVC_CODECS)
+        } else {
+            TRANSCODED_AVC_CODECS
+        };
+        let bandwidth = v.bandwidth.unwrap_or(0) + audio.bandwidth.unwrap_or(0);
+        let mut attrs = vec![format!("BANDWIDTH={}", bandwidth)];
+        if let (Some(w), Some(h)) = (v.width, v.height) {
+            attrs.push(format!("RESOLUTION={}x{}", w, h));
+        }
+        attrs.push(format!("CODECS=\"{},{}\"", video_codecs, muxed_codecs));
+        if let Some(fps) = v
+            .frame_rate
+            .as_deref()
+            .and_then(|s| s.parse::<f64>().ok())
+            .filter(|f| *f > 0.0)
+        {
+            attrs.push(format!("FRAME-RATE={:.3}", fps));
+        }
+        if audio_group {
+            attrs.push("AUDIO=\"aud\"".to_string());
+        }
+        out.push_str(&format!(
+            "#EXT-X-STREAM-INF:{}\n{}/index.m3u8\n",
+            attrs.join(","),
+            Variant::of(v, format, audio_kind).key()
+        ));
+    }
+    Ok(out)
+}
+
+/// remux 模式的 master：与转码模式一样按画质偏好筛选视频轨并选择默认音轨
+fn render_remux_master(dash: &PlayurlDash, profile: Profile) -> Result<String> {
+    let audio = select_audio(dash, profile.audio)?;
+    remux::render_master_playlist(dash, &profile.videos(dash), audio)
+}
+
+/// durl 只有一个画质，master 中仅一个变体；带宽按总大小与时长估算
+fn render_durl_master(durl: &PlayurlDurl, format: SegmentFormat) -> String {
+    let size: u64 = durl.segments.iter().map(|s| s.size).sum();
+    let bandwidth = size * 8 * 1000 / durl.timelength.max(1);
+    let version = match format {
+        SegmentFormat::Ts => 3,
+        SegmentFormat::Fmp4 | SegmentFormat::Remux => 7,
+    };
+    format!(
+        "#EXTM3U\n#EXT-X-VERSION:{}\n#EXT-X-INDEPENDENT-SEGMENTS\n#EXT-X-STREAM-INF:BANDWIDTH={},CODECS=\"{},mp4a.40.2\"\n{}/index.m3u8\n",
+        version,
+        bandwidth,
+        TRANSCODED_AVC_CODECS,
+        Variant::durl(durl, format).key()
+    )
+}
+
+/// 默认变体按格式与画质偏好写入 `.default-{format}-{profile}`，避免兼容入口每次轮询都请求上游
+async fn default_variant(
+    client: &reqwest::Client,
+    season_id: &str,
+    sort: &str,
+    format: SegmentFormat,
+    profile: Profile,
+) -> Result<Variant> {
+    let marker = episode_dir(season_id, sort).join(format!(
+        ".default-{}-{}",
+        format.as_str(),
+        profile.key()
+    ));
+    if let Ok(s) = tokio::fs::read_to_string(&marker).await
+        && let Some(v) = Variant::parse(s.trim())
+    {
+        return Ok(v);
+    }
+    let dash = load_dash(client, season_id, sort).await?;
+    if let Some(durl) = &dash.durl {
+        return Ok(Variant::durl(durl, format));
+    }
+    let video = profile.pick_video(&dash, format)?;
+    let audio = select_audio(&dash, profile.audio)?;
+    let variant = Variant::of(video, format, AudioPref::of(audio));
+    if let Some(parent) = marker.parent() {
+        tokio::fs::create_dir_all(parent).await.ok();
+    }
+    tokio::fs::write(&marker, variant.key()).await.ok();
+    Ok(variant)
+}
+
+/// 将变体 playlist 中的分片地址（含 fMP4 的 EXT-X-MAP）改写为相对剧集目录的 `{variant}/{uri}`
+fn prefix_playlist_uris(content: &str, variant: &str) -> String {
+    content
+        .lines()
+        .map(|line| {
+            if line.starts_with("#EXT-X-MAP:URI=\"") {
+                line.replacen("URI=\"", &format!("URI=\"{}/", variant), 1)
+            } else if line.is_empty() || line.starts_with('#') {
+                line.to_string()
+            } else {
+                format!("{}/{}", variant, line)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        + "\n"
+}
+
+/// 变体的输入轨
+#[allow(clippy::large_enum_variant)]
+enum Tracks {
+    /// DASH 音视频分离轨；纯音频 rendition 无视频轨
+    Dash {
+        video: Option<PlayVideo>,
+        audio: PlayAudio,
+    },
+    /// durl 分段，音视频已混流
+    Durl(PlayurlDurl),
+}
+
+/// 为变体选择音视频轨，同时返回时长（秒）用于生成 VOD playlist
+async fn select_tracks(
+    client: &reqwest::Client,
+    info: &EpisodeInfo,
+    variant: Variant,
+) -> Result<(Tracks, u64)> {
+    let dash = fetch_dash(client, info).await?;
+    let duration = dash
+        .duration
+        .filter(|d| *d > 0)
+        .ok_or_else(|| anyhow!("dash 未返回时长"))?;
+    if let Some(durl) = dash.durl {
+        if Variant::durl(&durl, variant.format) != variant {
+            return Err(anyhow!("变体不存在: {}", variant.key()));
+        }
+        return Ok((Tracks::Durl(durl), duration));
+    }
+    let video = match variant.video {
+        Some(_) => Some(
+            dash.video
+                .iter()
+                .find(|v| variant.matches(v))
+                .cloned()
+                .ok_or_else(|| anyhow!("变体不存在: {}", variant.key()))?,
+        ),
+        None => None,
+    };
+    let audio = select_audio(&dash, variant.audio)?.clone();
+    Ok((Tracks::Dash { video, audio }, duration))
+}
+
+/// durl 分段的 ffconcat 列表：HTTP 请求头通过 option 指令逐段设置（需 FFmpeg 5.0+），
+/// 写明每段时长，拖动时无需逐段探测；登录 Cookie 与 DASH 输入一样随请求发送
+fn render_ffconcat(durl: &PlayurlDurl, urls: &[String], cookie: Option<&str>) -> String {
+    let quote = |s: &str| format!("'{}'", s.replace('\'', "'\\''"));
+    let mut out = String::from("ffconcat version 1.0\n");
+    for (seg, url) in durl.segments.iter().zip(urls) {
+        out.push_str(&format!("file {}\n", quote(url)));
+        out.push_str(&format!("option user_agent {}\n", quote(USER_AGENT)));
+        out.push_str("option referer 'https://www.bilibili.com'\n");
+        if let Some(c) = cookie.filter(|c| !c.is_empty()) {
+            // 单行头部，FFmpeg 会自行补上结尾的 CRLF
+            out.push_str(&format!(
+                "option headers {}\n",
+                quote(&format!("Cookie: {}", c))
+            ));
+        }
+        if seg.length > 0 {
+            out.push_str(&format!("duration {:.3}\n", seg.length as f64 / 1000.0));
+        }
+    }
+    out
+}
+
+pub(crate) fn segment_name(format: SegmentFormat, n: u64) -> String {
+    match format {
+        SegmentFormat::Ts => format!("{:010}.ts", n),
+        SegmentFormat::Fmp4 | SegmentFormat::Remux => format!("{:010}.m4s", n),
+    }
+}
+
+/// 片头或片尾的区间（秒）
+#[derive(Debug, Clone, Copy, Serialize)]
+pub(crate) struct SkipRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+/// 番剧接口每集 `skip.op` / `skip.ed` 给出的片头片尾区间，未标注时为 None
+#[derive(Debug, Clone, Default, Serialize)]
+pub(crate) struct EpisodeSkip {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub op: Option<SkipRange>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ed: Option<SkipRange>,
+}
+
+impl EpisodeSkip {
+    pub(crate) fn from_episode(ep: &Episode) -> Self {
+        // 未标注时接口返回 0-0
+        let range = |span: Option<SkipSpan>| {
+            span.and_then(SkipSpan::marked).map(|s| SkipRange {
+                start: s.start,
+                end: s.end,
+            })
+        };
+        let skip = ep.skip.as_ref();
+        Self {
+            op: range(skip.and_then(|s| s.op)),
+            ed: range(skip.and_then(|s| s.ed)),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.op.is_none() && self.ed.is_none()
+    }
+}
+
+/// 以 1970-01-01T00:00:00Z 为节目起点的 ISO 8601 时间，供 EXT-X-DATERANGE 使用
+fn program_date(secs: u64) -> String {
+    format!(
+        "1970-01-01T{:02}:{:02}:{:02}.000Z",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// 片头片尾写为 `CLASS="com.selfani.skip"` 的 EXT-X-DATERANGE；
+/// DATERANGE 依赖 EXT-X-PROGRAM-DATE-TIME，因此以 Unix 纪元作为第一个分片的时间
+fn render_skip_ranges(skip: &EpisodeSkip) -> String {
+    if skip.is_empty() {
+        return String::new();
+    }
+    let mut out = format!("#EXT-X-PROGRAM-DATE-TIME:{}\n", program_date(0));
+    for (id, range) in [("op", skip.op), ("ed", skip.ed)] {
+        if let Some(r) = range {
+            out.push_str(&format!(
+                "#EXT-X-DATERANGE:ID=\"{}\",CLASS=\"com.selfani.skip\",START-DATE=\"{}\",DURATION={}.000,X-SKIP-TYPE=\"{}\"\n",
+                id,
+                program_date(r.start),
+                r.end - r.start,
+                id
+            ));
+        }
+    }
+    out
+}
+
+/// 转码或纯音频时按 SEGMENT_SECONDS 等分，FFmpeg 在同样的时间点强制关键帧，边界完全对齐
+fn fixed_segments(duration: u64) -> Vec<f64> {
+    (0..duration.div_ceil(SEGMENT_SECONDS))
+        .map(|n| (duration - n * SEGMENT_SECONDS).min(SEGMENT_SECONDS) as f64)
+        .collect()
+}
+
+/// 直接复制视频时只能在源关键帧处切分：sidx 的每个引用都从关键帧开始，分片与引用一一对应。
+/// 一个引用内可能还有其它关键帧（如场景切换），FFmpeg 在那里多切出的小分片由 merge_parts 合并回来
+fn keyframe_segments(index: &TrackIndex) -> Vec<f64> {
+    index.segments.iter().map(|s| s.duration).collect()
+}
+
+/// 读取已生成的 VOD playlist 中各分片的时长，重启 FFmpeg 时据此计算起始时间
+fn playlist_segments(content: &str) -> Vec<f64> {
+    content
+        .lines()
+        .filter_map(|line| line.strip_prefix("#EXTINF:"))
+        .filter_map(|rest| rest.split(',').next()?.parse().ok())
+        .collect()
+}
+
+/// 读取 FFmpeg 写出的列表：各小分片的 (时长, 文件名)，以及是否已写完（EXT-X-ENDLIST）
+fn ffmpeg_playlist_entries(content: &str) -> (Vec<(f64, String)>, bool) {
+    let mut entries = Vec::new();
+    let mut duration = None;
+    for line in content.lines().map(str::trim) {
+        if let Some(rest) = line.strip_prefix("#EXTINF:") {
+            duration = rest.split(',').next().and_then(|d| d.parse().ok());
+        } else if !line.is_empty()
+            && !line.starts_with('#')
+            && let Some(d) = duration.take()
+        {
+            entries.push((d, line.to_string()));
+        }
+    }
+    (entries, content.contains("#EXT-X-ENDLIST"))
+}
+
+/// 把变体目录下各次运行已写完的小分片合并为正式分片。
+/// 小分片都从关键帧开始，而正式分片的边界（sidx 引用的起点）也是关键帧，
+/// 因此每个正式分片恰好由起点落在其区间内的若干个连续小分片组成。
+/// 只合并后面已有小分片（或 FFmpeg 已结束）的分片；全部合并完的目录随之删除
+pub(crate) fn merge_parts(work_dir: &Path, format: SegmentFormat) {
+    let Ok(entries) = std::fs::read_dir(work_dir) else {
+        return;
+    };
+    for dir in entries.flatten().map(|e| e.path()) {
+        let is_parts = dir
+            .file_name()
+            .is_some_and(|n| n.to_string_lossy().starts_with(PARTS_DIR_PREFIX));
+        if is_parts && merge_run(work_dir, &dir, format) {
+            let _ = std::fs::remove_dir_all(&dir);
+        }
+    }
+}
+
+/// 合并一次运行的小分片，全部合并完时返回 true
+fn merge_run(work_dir: &Path, parts_dir: &Path, format: SegmentFormat) -> bool {
+    let start_number = std::fs::read_to_string(parts_dir.join(PARTS_START_FILE))
+        .ok()
+        .and_then(|s| s.trim().parse::<usize>().ok());
+    let (Some(start_number), Ok(list), Ok(index)) = (
+        start_number,
+        std::fs::read_to_string(parts_dir.join("ffmpeg.m3u8")),
+        std::fs::read_to_string(work_dir.join("index.m3u8")),
+    ) else {
+        return false;
+    };
+    let (parts, ended) = ffmpeg_playlist_entries(&list);
+    let segments = playlist_segments(&index);
+    if parts.is_empty() || start_number >= segments.len() {
+        return ended;
+    }
+    // 列表中出现小分片时 init 段已写完
+    let init = work_dir.join("init.mp4");
+    if format != SegmentFormat::Ts && !init.exists() {
+        let tmp = parts_dir.join("init.mp4.tmp");
+        if std::fs::copy(parts_dir.join("init.mp4"), &tmp)
+            .and_then(|_| std::fs::rename(&tmp, &init))
+            .is_err()
+        {
+            return false;
+        }
+    }
+    let mut part_start: f64 = segments[..start_number].iter().sum();
+    let mut segment_end = part_start;
+    let mut next = 0;
+    for (n, len) in segments.iter().enumerate().skip(start_number) {
+        segment_end += len;
+        let last = n + 1 == segments.len();
+        let first = next;
+        while next < parts.len() && (last || part_start < segment_end - PART_BOUNDARY_TOLERANCE) {
+            part_start += parts[next].0;
+            next += 1;
+        }
+        // 后面还没有小分片时，最后一个小分片可能仍在写入范围内
+        if next == parts.len() && !ended {
+            return false;
+        }
+        if first == next {
+            // FFmpeg 已结束但没有产出这一段，交由 supervisor 按提前结束处理
+            return ended;
+        }
+        let target = work_dir.join(segment_name(format, n as u64));
+        if !target.exists() {
+            let tmp = parts_dir.join(format!("{}.tmp", segment_name(format, n as u64)));
+            let merged = parts[first..next]
+                .iter()
+                .try_fold(Vec::new(), |mut buf, (_, name)| {
+                    buf.extend(std::fs::read(parts_dir.join(name))?);
+                    Ok::<_, std::io::Error>(buf)
+                })
+                .and_then(|buf| std::fs::write(&tmp, buf))
+                .and_then(|_| std::fs::rename(&tmp, &target));
+            // 小分片已被并发的合并删除时留给下一轮
+            if merged.is_err() {
+                return false;
+            }
+        }
+        for (_, name) in &parts[first..next] {
+            let _ = std::fs::remove_file(parts_dir.join(name));
+        }
+    }
+    true
+}
+
+/// 在后台持续合并一次运行的小分片，直到全部合并、目录被删除或 FFmpeg 长时间没有新输出
+fn spawn_part_merger(work_dir: PathBuf, parts_dir: PathBuf, format: SegmentFormat) {
+    tokio::spawn(async move {
+        let list = parts_dir.join("ffmpeg.m3u8");
+        let mut last_change = (None, Instant::now());
+        loop {
+            sleep(PART_POLL_INTERVAL).await;
+            if !parts_dir.is_dir() {
+                return;
+            }
+            if merge_run(&work_dir, &parts_dir, format) {
+                let _ = std::fs::remove_dir_all(&parts_dir);
+                return;
+            }
+            let modified = std::fs::metadata(&list).and_then(|m| m.modified()).ok();
+            if modified != last_change.0 {
+                last_change = (modified, Instant::now());
+            } else if last_change.1.elapsed() >= PART_IDLE_TIMEOUT {
+                return;
+            }
+        }
+    });
+}
+
+/// 视频轨的 sidx 索引，依次尝试各镜像
+async fn video_track_index(client: &reqwest::Client, video: &PlayVideo) -> Result<TrackIndex> {
+    let mut last_err = anyhow!("无可用地址");
+    for url in cdn::candidates(&video.base_url, video.backup_url.as_deref()) {
+        match remux::fetch_track_index(client, &url, video.segment_base.as_ref()).await {
+            Ok(index) => return Ok(index),
+            Err(e) => last_err = e,
+        }
+    }
+    Err(last_err)
+}
+
+/// 按分片时长预先生成完整的 VOD playlist
+fn render_vod_playlist(segments: &[f64], format: SegmentFormat, skip: &EpisodeSkip) -> String {
+    let version = match format {
+        SegmentFormat::Ts => 3,
+        SegmentFormat::Fmp4 | SegmentFormat::Remux => 7,
+    };
+    let target = segments
+        .iter()
+        .copied()
+        .fold(0.0_f64, f64::max)
+        .ceil()
+        .max(1.0) as u64;
+    let mut out = format!(
+        "#EXTM3U\n#EXT-X-VERSION:{}\n#EXT-X-TARGETDURATION:{}\n#EXT-X-MEDIA-SEQUENCE:0\n#EXT-X-PLAYLIST-TYPE:VOD\n#EXT-X-INDEPENDENT-SEGMENTS\n",
+        version, target
+    );
+    if format != SegmentFormat::Ts {
+        out.push_str("#EXT-X-MAP:URI=\"init.mp4\"\n");
+    }
+    out.push_str(&render_skip_ranges(skip));
+    for (n, len) in segments.iter().enumerate() {
+        // 保留 6 位小数，重启时由各分片时长累加出的起始时间不会偏离关键帧
+        out.push_str(&format!(
+            "#EXTINF:{:.6},\n{}\n",
+            len,
+            segment_name(format, n as u64)
+        ));
+    }
+    out.push_str("#EXT-X-ENDLIST\n");
+    out
+}
+
+/// 确保变体的 VOD playlist 已生成；首次生成时从头启动 FFmpeg
 async fn prepare_hls_pipeline(
     client: &reqwest::Client,
     season_id: &str,
     sort: &str,
+    variant: Variant,
 ) -> Result<PathBuf> {
-    let season_id_num: i64 = season_id.parse()?;
-    let sort_num: usize = sort.parse()?;
-    let cfg = config::get();
-    let base_cache = PathBuf::from(&cfg.api.cache_dir).join("hls");
-    tokio::fs::create_dir_all(&base_cache).await.ok();
-    let work_dir = base_cache.join(season_id).join(sort);
-    tokio::fs::create_dir_all(&work_dir).await?;
+    let work_dir = episode_dir(season_id, sort).join(variant.key());
+    cache::reconcile_dir(
+        &work_dir,
+        supervisor::is_active(&job_key(season_id, sort, variant)),
+    );
     let playlist = work_dir.join("index.m3u8");
     if playlist.exists() {
+        cache::mark_access(&work_dir);
         return Ok(work_dir);
     }
 
-    // 防止并发重复启动，使用锁文件
-    let lock_path = work_dir.join(".lock");
-    let created = match tokio::fs::OpenOptions::new()
-        .write(true)
-        .create_new(true)
-        .open(&lock_path)
-        .await
-    {
-        Ok(_) => true,
-        Err(_) => false,
+    // 剧集与音视频轨只解析一次，首个任务直接复用
+    let info = resolve_episode(client, season_id, sort).await?;
+    let (tracks, duration) = select_tracks(client, &info, variant).await?;
+    let segments = match &tracks {
+        Tracks::Dash {
+            video: Some(video), ..
+        } if variant.format.can_copy(video.codecid) => {
+            keyframe_segments(&video_track_index(client, video).await?)
+        }
+        _ => fixed_segments(duration),
     };
-    if !created {
-        return Ok(work_dir);
-    } // 其它并发请求已在生成
-
-    // 获取 episode -> dash
-    let (ep_id, _aid, _cid) = fetch_episode_ids(client, season_id_num, sort_num).await?;
-    let dash = playurl::fetch_dash_pgc(client, ep_id, season_id_num as u64, true).await?;
-    // 选择视频轨：最高带宽（最高画质）；如果不是 AVC(codecid=7) 则后续转码到 H.264
-    let video_list = dash.video.clone();
-    if video_list.is_empty() {
-        return Err(anyhow!("无视频轨"));
+    tokio::fs::create_dir_all(&work_dir).await?;
+    // 先写临时文件再改名，避免并发请求读到半个 playlist
+    let tmp = work_dir.join("index.m3u8.tmp");
+    tokio::fs::write(
+        &tmp,
+        render_vod_playlist(&segments, variant.format, &info.skip),
+    )
+    .await?;
+    tokio::fs::rename(&tmp, &playlist).await?;
+    cache::mark_access(&work_dir);
+
+    ensure_job(client, season_id, sort, variant, &work_dir, 0, Some(tracks)).await?;
+    Ok(work_dir)
+}
+
+fn job_key(season_id: &str, sort: &str, variant: Variant) -> JobKey {
+    JobKey {
+        season_id: season_id.to_string(),
+        sort: sort.to_string(),
+        variant: variant.key(),
     }
-    let video = video_list
-        .iter()
-        .max_by_key(|v| v.bandwidth.unwrap_or(0))
-        .unwrap()
-        .clone();
-    let need_transcode = video.codecid != Some(7);
-    if need_transcode {
-        log::info!(
-            "最高画质轨需要转码: id={} codecid={:?} bandwidth={} width={:?} height={:?}",
+}
+
+/// 确保有 FFmpeg 任务能在短时间内产出 `segment`：
+/// 若当前任务的进度已覆盖或即将覆盖该分片则等待，否则终止旧任务并从该分片处重启。
+/// 任务交由 supervisor 排队启动，这里不等待其真正运行。
+/// 调用方已选好音视频轨时通过 `tracks` 传入，否则在此重新解析。
+async fn ensure_job(
+    client: &reqwest::Client,
+    season_id: &str,
+    sort: &str,
+    variant: Variant,
+    work_dir: &Path,
+    segment: u64,
+    tracks: Option<Tracks>,
+) -> Result<()> {
+    let key = job_key(season_id, sort, variant);
+    let Some(job_id) =
+        supervisor::claim(&key, work_dir, variant.format, segment, SEEK_RESTART_GAP)?
+    else {
+        return Ok(());
+    };
+    // 分片划分以已生成的 playlist 为准
+    let segments = match tokio::fs::read_to_string(work_dir.join("index.m3u8")).await {
+        Ok(content) => playlist_segments(&content),
+        Err(e) => {
+            supervisor::abandon(&key, job_id);
+            return Err(anyhow!("playlist 未生成: {e}"));
+        }
+    };
+    let tracks = match tracks {
+        Some(tracks) => tracks,
+        None => {
+            let selected = match resolve_episode(client, season_id, sort).await {
+                Ok(info) => select_tracks(client, &info, variant).await,
+                Err(e) => Err(e),
+            };
+            match selected {
+                Ok((tracks, _)) => tracks,
+                Err(e) => {
+                    supervisor::abandon(&key, job_id);
+                    return Err(e);
+                }
+            }
+        }
+    };
+    let work_dir = work_dir.to_path_buf();
+    let task_key = key.to_string();
+    let total_segments = segments.len() as u64;
+    let start_time = move |n: u64| segments.iter().take(n as usize).sum::<f64>();
+    let (video, audio) = match tracks {
+        Tracks::Dash { video, audio } => (video, audio),
+        Tracks::Durl(durl) => {
+            log::info!(
+                "选择 durl: variant={} format={} start={} quality={} container={} segments={} mode=transcode(h264)",
+                variant.key(),
+                variant.format.as_str(),
+                segment,
+                durl.quality,
+                durl.format,
+                durl.segments.len()
+            );
+            let cookie = build_cookie_string();
+            // 分段可能很多，不逐一探测；每次重试所有分段一起换到下一个镜像
+            let mirrors: Vec<Vec<String>> = durl
+                .segments
+                .iter()
+                .map(|s| cdn::candidates(&s.url, s.backup_url.as_deref()))
+                .collect();
+            let attempts = mirrors.iter().map(Vec::len).max().unwrap_or(1);
+            tokio::spawn(supervisor::launch(
+                key,
+                job_id,
+                total_segments,
+                attempts,
+                move |attempt, start_number| {
+                    let urls: Vec<String> = mirrors
+                        .iter()
+                        .map(|m| m[attempt % m.len()].clone())
+                        .collect();
+                    log::info!(
+                        "使用 CDN: durl {} ({})",
+                        urls.first().map_or("-".to_string(), |u| cdn::host(u)),
+                        task_key
+                    );
+                    let list = work_dir.join(CONCAT_FILE);
+                    std::fs::write(&list, render_ffconcat(&durl, &urls, cookie.as_deref()))?;
+                    run_ffmpeg_hls(
+                        Input::Concat(&list),
+                        &work_dir,
+                        variant,
+                        start_number,
+                        start_time(start_number),
+                    )
+                },
+            ));
+            return Ok(());
+        }
+    };
+    let audio_mode = if variant.format.transcode_audio(variant.audio) {
+        "transcode(aac)"
+    } else {
+        "copy"
+    };
+    match &video {
+        Some(video) => log::info!(
+            "选择视频: variant={} format={} start={} id={} codecid={:?} bandwidth={} width={:?} height={:?} mode={} | 音频: id={} bandwidth={} codecs={:?} mode={}",
+            variant.key(),
+            variant.format.as_str(),
+            segment,
             video.id,
             video.codecid,
             video.bandwidth.unwrap_or(0),
             video.width,
-            video.height
-        );
+            video.height,
+            if variant.format.can_copy(video.codecid) {
+                "copy"
+            } else {
+                "transcode(h264)"
+            },
+            audio.id,
+            audio.bandwidth.unwrap_or(0),
+            audio.codecs,
+            audio_mode
+        ),
+        None => log::info!(
+            "选择音频 rendition: variant={} format={} start={} id={} bandwidth={} codecs={:?} mode={}",
+            variant.key(),
+            variant.format.as_str(),
+            segment,
+            audio.id,
+            audio.bandwidth.unwrap_or(0),
+            audio.codecs,
+            audio_mode
+        ),
     }
-    let audio = dash
-        .audio
-        .iter()
-        .max_by_key(|a| a.bandwidth.unwrap_or(0))
-        .ok_or_else(|| anyhow::anyhow!("无音频轨"))?;
-
-    // 输出所选音视频参数
-    log::info!(
-        "选择视频: id={} codecid={:?} bandwidth={} width={:?} height={:?} mode={} | 音频: id={} bandwidth={} codecs={:?}",
-        video.id,
-        video.codecid,
-        video.bandwidth.unwrap_or(0),
-        video.width,
-        video.height,
-        if need_transcode {
-            "transcode(h264)"
-        } else {
-            "copy"
-        },
-        audio.id,
-        audio.bandwidth.unwrap_or(0),
-        audio.codecs
-    );
-
-    let v_url = video.base_url.clone();
-    let a_url = audio.base_url.clone();
 
     // 预构造 UA & Cookie 头（失败不致命）
-    let ua = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
     let cookie_header = build_cookie_string();
-    let extra_headers = build_ffmpeg_headers(ua, cookie_header.as_deref());
+    let extra_headers = build_ffmpeg_headers(USER_AGENT, cookie_header.as_deref());
 
-    // 使用 FFmpeg 命令行直接处理
-    let playlist_path = playlist.clone();
-    let work_dir_clone = work_dir.clone();
-    tokio::spawn(async move {
-        let cleanup_lock = lock_path.clone();
-        if let Err(e) = run_ffmpeg_hls(
-            &v_url,
-            &a_url,
-            &work_dir_clone,
-            &playlist_path,
-            &extra_headers,
-            !need_transcode,
-        )
-        .await
-        {
-            log::error!("启动 FFmpeg 失败: {e}");
-            let _ = fs::remove_file(&playlist_path); // 失败时移除空的 playlist
+    // 探测 base_url 与 backup_url，延迟最低的镜像优先，失败时依次换下一个
+    let video_urls = match &video {
+        Some(v) => {
+            cdn::rank(
+                client,
+                cdn::candidates(&v.base_url, v.backup_url.as_deref()),
+            )
+            .await
         }
-        let _ = fs::remove_file(&cleanup_lock);
-    });
+        None => Vec::new(),
+    };
+    let audio_urls = cdn::rank(
+        client,
+        cdn::candidates(&audio.base_url, audio.backup_url.as_deref()),
+    )
+    .await;
+    let attempts = video_urls.len().max(audio_urls.len());
 
-    Ok(work_dir)
+    tokio::spawn(supervisor::launch(
+        key,
+        job_id,
+        total_segments,
+        attempts,
+        move |attempt, start_number| {
+            let video_url =
+                (!video_urls.is_empty()).then(|| video_urls[attempt % video_urls.len()].as_str());
+            let audio_url = &audio_urls[attempt % audio_urls.len()];
+            log::info!(
+                "使用 CDN: 视频 {} | 音频 {} ({})",
+                video_url.map_or("-".to_string(), cdn::host),
+                cdn::host(audio_url),
+                task_key
+            );
+            run_ffmpeg_hls(
+                Input::Dash {
+                    video: video_url,
+                    audio: audio_url,
+                    headers: &extra_headers,
+                },
+                &work_dir,
+                variant,
+                start_number,
+                start_time(start_number),
+            )
+        },
+    ));
+    Ok(())
 }
 
-async fn run_ffmpeg_hls(
-    video_url: &str,
-    audio_url: &str,
+/// FFmpeg 的输入
+enum Input<'a> {
+    /// DASH 分离轨；`video` 为 None 时只输出音频（备选音轨 rendition）
+    Dash {
+        video: Option<&'a str>,
+        audio: &'a str,
+        headers: &'a str,
+    },
+    /// durl 分段的 ffconcat 列表，音视频已混流，请求头写在列表内
+    Concat(&'a Path),
+}
+
+/// 启动 FFmpeg 从第 `start_number` 个分片（起始于 `start_time` 秒）开始输出变体分片
+fn run_ffmpeg_hls(
+    input: Input,
     work_dir: &Path,
-    playlist_path: &Path,
-    headers: &str,
-    can_copy_video: bool,
-) -> Result<()> {
-    // 使用 FFmpeg 直接从 URL 下载并合流，输出为 HLS。改为 spawn，实时写出 index.m3u8 与分片。
-    let output_pattern = work_dir.join("%010d.ts");
+    variant: Variant,
+    start_number: u64,
+    start_time: f64,
+) -> Result<Child> {
+    let format = variant.format;
+    let codecid = variant.codecid();
+    let audio_only = matches!(input, Input::Dash { video: None, .. });
+    let concat = matches!(input, Input::Concat(_));
+    // durl 分段没有关键帧索引，只能转码并强制关键帧
+    let copy_video = !audio_only && !concat && format.can_copy(codecid);
+    // 使用 FFmpeg 直接从 URL 下载并合流，输出为 HLS 分片；playlist 由我们预先生成，FFmpeg 自己的列表仅作参考。
+    // 复制视频时先在单独的目录中按关键帧切出小分片，由 merge_parts 合并为正式分片
+    let out_dir = if copy_video {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        let dir = work_dir.join(format!("{}{}", PARTS_DIR_PREFIX, nanos));
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join(PARTS_START_FILE), start_number.to_string())?;
+        dir
+    } else {
+        work_dir.to_path_buf()
+    };
+    let output_pattern = match format {
+        SegmentFormat::Ts => out_dir.join("%010d.ts"),
+        SegmentFormat::Fmp4 | SegmentFormat::Remux => out_dir.join("%010d.m4s"),
+    };
+    // 从指定分片开始时对两个输入做 seek，并平移输出时间戳，使分片能与之前的分片无缝衔接
+    let seek = if copy_video {
+        start_time + KEYFRAME_SEEK_EPSILON
+    } else {
+        start_time
+    };
+    let seek = format!("{:.6}", seek);
+    let offset = format!("{:.6}", start_time);
     let mut cmd = Command::new("ffmpeg");
-    cmd.arg("-hide_banner")
-        .arg("-loglevel")
-        .arg("warning") // 保留告警，便于排查
-        // 为每个输入附加头
-        .arg("-headers")
-        .arg(headers)
-        .arg("-i")
-        .arg(video_url)
-        .arg("-headers")
-        .arg(headers)
-        .arg("-i")
-        .arg(audio_url);
-
-    if can_copy_video {
+    cmd.arg("-hide_banner").arg("-loglevel").arg("warning"); // 保留告警，便于排查
+    match input {
+        Input::Dash {
+            video,
+            audio,
+            headers,
+        } => {
+            for url in video.into_iter().chain([audio]) {
+                // 为每个输入附加头
+                cmd.arg("-headers").arg(headers);
+                if start_number > 0 {
+                    cmd.arg("-ss").arg(&seek);
+                }
+                cmd.arg("-i").arg(url);
+            }
+        }
+        Input::Concat(list) => {
+            if start_number > 0 {
+                cmd.arg("-ss").arg(&seek);
+            }
+            cmd.arg("-f")
+                .arg("concat")
+                .arg("-safe")
+                .arg("0")
+                .arg("-protocol_whitelist")
+                .arg("file,http,https,tcp,tls,crypto")
+                .arg("-i")
+                .arg(list);
+        }
+    }
+
+    if audio_only {
+        cmd.arg("-vn");
+    } else if copy_video {
         cmd.arg("-c:v").arg("copy");
+        if format != SegmentFormat::Ts && codecid == Some(12) {
+            // HEVC 以 hvc1 标记写入，Apple 系播放器才能识别
+            cmd.arg("-tag:v").arg("hvc1");
+        }
     } else {
         cmd.arg("-c:v")
             .arg("libx264")
@@ -206,53 +1571,62 @@ async fn run_ffmpeg_hls(
             .arg("4.1")
             .arg("-sc_threshold")
             .arg("0")
-            .arg("-g")
-            .arg("60")
-            .arg("-keyint_min")
-            .arg("60")
             .arg("-force_key_frames")
-            .arg("expr:gte(t,n_forced*2)");
-    }
-    cmd.arg("-c:a")
-        .arg("copy")
-        .arg("-map")
-        .arg("0:v:0")
-        .arg("-map")
-        .arg("1:a:0")
-        .arg("-f")
+            .arg(format!("expr:gte(t,n_forced*{})", SEGMENT_SECONDS));
+    }
+    if start_number > 0 {
+        cmd.arg("-output_ts_offset").arg(&offset);
+    }
+    if format.transcode_audio(variant.audio) {
+        // MPEG-TS 无法承载 FLAC，转为高码率 AAC
+        cmd.arg("-c:a").arg("aac").arg("-b:a").arg("320k");
+    } else {
+        cmd.arg("-c:a").arg("copy");
+        if variant.audio == AudioPref::Flac {
+            // 旧版 FFmpeg 写入 MP4 的 FLAC 仍标记为实验性
+            cmd.arg("-strict").arg("experimental");
+        }
+    }
+    if audio_only {
+        cmd.arg("-map").arg("0:a:0");
+    } else if concat {
+        cmd.arg("-map").arg("0:v:0").arg("-map").arg("0:a:0");
+    } else {
+        cmd.arg("-map").arg("0:v:0").arg("-map").arg("1:a:0");
+    }
+    cmd.arg("-f")
         .arg("hls")
         .arg("-hls_time")
-        .arg("2")
+        .arg(if copy_video {
+            KEYFRAME_SPLIT_TIME.to_string()
+        } else {
+            SEGMENT_SECONDS.to_string()
+        })
         .arg("-hls_list_size")
         .arg("0")
         .arg("-hls_segment_type")
-        .arg("mpegts")
-        .arg("-hls_flags")
-        .arg("independent_segments+delete_segments") // 允许独立关键帧，必要时清理
+        .arg(match format {
+            SegmentFormat::Ts => "mpegts",
+            SegmentFormat::Fmp4 | SegmentFormat::Remux => "fmp4",
+        });
+    if format != SegmentFormat::Ts {
+        cmd.arg("-hls_fmp4_init_filename").arg("init.mp4");
+    }
+    cmd.arg("-hls_flags")
+        .arg("independent_segments+temp_file") // 分片写完才改名，出现即完整
         .arg("-hls_segment_filename")
         .arg(output_pattern.to_string_lossy().as_ref())
         .arg("-start_number")
-        .arg("0")
+        .arg(if copy_video { 0 } else { start_number }.to_string())
         .arg("-y")
-        .arg(playlist_path.to_string_lossy().as_ref());
+        .arg(out_dir.join("ffmpeg.m3u8").to_string_lossy().as_ref());
 
-    log::info!("启动 FFmpeg (实时 HLS) cmd={:?}", cmd);
-    let child = cmd.spawn()?; // 不等待完成
-    // 可选：在后台等待并记录退出状态
-    tokio::spawn(async move {
-        match child.wait_with_output().await {
-            Ok(out) => {
-                if !out.status.success() {
-                    let stderr = String::from_utf8_lossy(&out.stderr);
-                    log::error!("FFmpeg 退出非 0: {}", stderr);
-                } else {
-                    log::info!("FFmpeg 结束，HLS 生成完成");
-                }
-            }
-            Err(e) => log::error!("等待 FFmpeg 退出失败: {e}"),
-        }
-    });
-    Ok(())
+    log::info!("启动 FFmpeg (VOD HLS) cmd={:?}", cmd);
+    let child = cmd.kill_on_drop(true).spawn()?; // 不等待完成
+    if copy_video {
+        spawn_part_merger(work_dir.to_path_buf(), out_dir, format);
+    }
+    Ok(child)
 }
 
 // 构造 ffmpeg -headers 需要的多行头（以 CRLF 分隔，并末尾再追加一个 CRLF）
@@ -262,10 +1636,10 @@ fn build_ffmpeg_headers(user_agent: &str, cookie: Option<&str>) -> String {
     lines.push("Referer: https://www.bilibili.com".to_string());
     lines.push("Origin: https://www.bilibili.com".to_string());
     lines.push(format!("User-Agent: {}", user_agent));
-    if let Some(c) = cookie {
-        if !c.is_empty() {
-            lines.push(format!("Cookie: {}", c));
-        }
+    if let Some(c) = cookie
+        && !c.is_empty()
+    {
+        lines.push(format!("Cookie: {}", c));
     }
     let mut s = lines.join("\r\n");
     s.push_str("\r\n\r\n"); // ffmpeg 要求末尾再加一个空行
@@ -280,13 +1654,13 @@ fn build_cookie_string() -> Option<String> {
     };
     let mut pairs: Vec<(String, String)> = Vec::new();
     for cookie in store.iter_any() {
-        if let Some(domain) = cookie.domain() {
-            if domain.ends_with("bilibili.com") || domain.ends_with("bilivideo.com") {
-                let name = cookie.name().to_string();
-                let value = cookie.value().to_string();
-                if !pairs.iter().any(|(n, _)| n == &name) {
-                    pairs.push((name, value));
-                }
+        if let Some(domain) = cookie.domain()
+            && (domain.ends_with("bilibili.com") || domain.ends_with("bilivideo.com"))
+        {
+            let name = cookie.name().to_string();
+            let value = cookie.value().to_string();
+            if !pairs.iter().any(|(n, _)| n == &name) {
+                pairs.push((name, value));
             }
         }
     }
@@ -316,57 +1690,111 @@ async fn wait_for_existing(path: &Path, retries: usize, interval_ms: u64) -> Res
     use tokio::fs::metadata;
     let mut last_nonzero = false;
     for _ in 0..retries {
-        if let Ok(meta) = metadata(path).await {
-            if meta.len() > 0 {
-                // 确保文件已写入数据
-                if last_nonzero {
-                    return Ok(());
-                }
-                last_nonzero = true; // 连续两次非空更保险
+        if let Ok(meta) = metadata(path).await
+            && meta.len() > 0
+        {
+            // 确保文件已写入数据
+            if last_nonzero {
+                return Ok(());
             }
+            last_nonzero = true; // 连续两次非空更保险
         }
         sleep(Duration::from_millis(interval_ms)).await;
     }
     Err(anyhow::anyhow!("timeout"))
 }
 
-async fn fetch_episode_ids(
+/// 按 sort（从 1 开始）取番剧接口中的某一集
+pub(crate) async fn fetch_episode(
     client: &reqwest::Client,
     season_id: i64,
     sort: usize,
-) -> Result<(u64, u64, u64)> {
-    let mut url = reqwest::Url::parse("https://api.bilibili.com/pgc/view/web/season")?;
-    url.query_pairs_mut()
-        .append_pair("season_id", &season_id.to_string());
-    let text = client
-        .get(url)
-        .header("Referer", "https://www.bilibili.com")
-        .send()
+) -> Result<Episode> {
+    season::get(client, season_id)
         .await?
-        .text()
-        .await?;
-    let v: Value = serde_json::from_str(&text)?;
-    let root = v
-        .get("result")
-        .or_else(|| v.get("data"))
-        .and_then(|r| r.get("episodes"))
-        .and_then(|e| e.as_array())
-        .ok_or_else(|| anyhow::anyhow!("episodes not found"))?;
-    let ep = root
-        .get(sort - 1)
-        .ok_or_else(|| anyhow::anyhow!("ep index out of range"))?;
-    let ep_id = ep
-        .get("ep_id")
-        .or_else(|| ep.get("id"))
-        .and_then(|v| v.as_u64())
-        .ok_or_else(|| anyhow::anyhow!("ep_id missing"))?;
-    let aid = ep
-        .get("aid")
-        .and_then(|v| v.as_u64())
-        .ok_or_else(|| anyhow::anyhow!("aid missing"))?;
-    let cid = ep
-        .get("cid")
-        .and_then(|v| v.as_u64())
-        .ok_or_else(|| anyhow::anyhow!("cid missing"))?;
-    Ok((ep_id, aid, cid))
+        .episode(sort)
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("ep index out of range"))
+}
+
+/// 按 ep_id 取剧集及其所属的 season_id
+async fn fetch_episode_by_id(client: &reqwest::Client, ep_id: u64) -> Result<(i64, Episode)> {
+    let season = season::get_by_ep(client, ep_id).await?;
+    let ep = season
+        .find_episode(ep_id)
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("ep not found ep_id={}", ep_id))?;
+    Ok((season.season_id, ep))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 在临时目录中模拟一次复制视频的运行：`segments` 为 playlist 的分片时长，
+    /// `parts` 为 FFmpeg 切出的小分片时长，内容依次为 a、b、c……
+    fn fake_run(name: &str, segments: &[f64], parts: &[f64], ended: bool) -> (PathBuf, PathBuf) {
+        let work_dir =
+            std::env::temp_dir().join(format!("selfani-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&work_dir);
+        let parts_dir = work_dir.join(format!("{}0", PARTS_DIR_PREFIX));
+        std::fs::create_dir_all(&parts_dir).unwrap();
+        let index = render_vod_playlist(segments, SegmentFormat::Ts, &EpisodeSkip::default());
+        std::fs::write(work_dir.join("index.m3u8"), index).unwrap();
+        std::fs::write(parts_dir.join(PARTS_START_FILE), "0").unwrap();
+        let mut list = String::from("#EXTM3U\n");
+        for (i, len) in parts.iter().enumerate() {
+            let file = format!("{:010}.ts", i);
+            std::fs::write(parts_dir.join(&file), [b'a' + i as u8]).unwrap();
+            list.push_str(&format!("#EXTINF:{:.6},\n{}\n", len, file));
+        }
+        if ended {
+            list.push_str("#EXT-X-ENDLIST\n");
+        }
+        std::fs::write(parts_dir.join("ffmpeg.m3u8"), list).unwrap();
+        (work_dir, parts_dir)
+    }
+
+    fn segment(work_dir: &Path, n: u64) -> Option<String> {
+        std::fs::read_to_string(work_dir.join(segment_name(SegmentFormat::Ts, n))).ok()
+    }
+
+    #[test]
+    fn merges_extra_keyframe_parts_into_one_segment() {
+        // 第一个 sidx 片段内有两个关键帧，FFmpeg 切出了 2.5 + 1.5 两个小分片
+        let (work_dir, parts_dir) =
+            fake_run("merge", &[4.0, 4.0, 2.0], &[2.5, 1.5, 4.0, 2.0], true);
+        merge_parts(&work_dir, SegmentFormat::Ts);
+        assert_eq!(segment(&work_dir, 0).as_deref(), Some("ab"));
+        assert_eq!(segment(&work_dir, 1).as_deref(), Some("c"));
+        assert_eq!(segment(&work_dir, 2).as_deref(), Some("d"));
+        assert!(!parts_dir.exists());
+        let _ = std::fs::remove_dir_all(&work_dir);
+    }
+
+    #[test]
+    fn waits_for_following_part_before_merging() {
+        // FFmpeg 仍在运行：最后一个小分片之后还可能有同一段的小分片
+        let (work_dir, parts_dir) = fake_run("pending", &[4.0, 4.0], &[2.5, 1.5, 3.0], false);
+        merge_parts(&work_dir, SegmentFormat::Ts);
+        assert_eq!(segment(&work_dir, 0).as_deref(), Some("ab"));
+        assert_eq!(segment(&work_dir, 1), None);
+        assert!(parts_dir.join("0000000002.ts").exists());
+        let _ = std::fs::remove_dir_all(&work_dir);
+    }
+
+    #[test]
+    fn parses_ffmpeg_playlist() {
+        let (entries, ended) = ffmpeg_playlist_entries(
+            "#EXTM3U\n#EXT-X-TARGETDURATION:3\n#EXTINF:2.502000,\n0000000000.ts\n#EXTINF:1.498000,\n0000000001.ts\n",
+        );
+        assert_eq!(
+            entries,
+            [
+                (2.502, "0000000000.ts".to_string()),
+                (1.498, "0000000001.ts".to_string())
+            ]
+        );
+        assert!(!ended);
+    }
 }
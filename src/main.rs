@@ -1,9 +1,20 @@
+mod bili;
+mod cache;
+mod cdn;
 mod config;
 mod cookies;
+mod danmaku;
+mod dub;
 mod hls;
 mod login;
 mod playurl;
+mod remux;
 mod search;
+mod season;
+mod series;
+mod subtitle;
+mod supervisor;
+mod ugc;
 mod wbi;
 
 use actix_cors::Cors;
@@ -12,7 +23,8 @@ use actix_web::{App, HttpRequest, HttpResponse, HttpServer, Responder, get, web}
 use anyhow::{Context, Result};
 use reqwest::Client;
 use serde::Serialize;
-use serde_json::Value;
+
+use crate::bili::season::{Episode, Season, Section};
 
 #[derive(Serialize)]
 pub struct ApiResult<T> {
@@ -32,6 +44,8 @@ struct SearchItem {
     status: String,
     #[serde(rename = "type")]
     type_field: String,
+    /// 番剧 / 影视 / 视频
+    category: String,
     url: String,
 }
 
@@ -39,7 +53,19 @@ struct SearchItem {
 struct DetailSourceItem {
     name: String,
     sort: usize,
+    /// 普通视频没有 ep_id
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ep_id: Option<u64>,
+    aid: u64,
+    cid: u64,
+    bvid: String,
     m3u8: String,
+    /// 相对服务根的播放列表路径，m3u8 = public_base + path
+    #[serde(skip)]
+    path: String,
+    /// 片头片尾区间（秒），未标注时省略
+    #[serde(skip_serializing_if = "hls::EpisodeSkip::is_empty")]
+    skip: hls::EpisodeSkip,
 }
 
 #[derive(Serialize)]
@@ -53,6 +79,24 @@ struct DetailData {
     #[serde(rename = "type")]
     type_field: String,
     sources: Vec<DetailSourceItem>,
+    /// 仅配音不同的其它 season（中配、粤配等），每个在 HTML 中为一个单独的线路
+    dubs: Vec<DetailDub>,
+    /// 番剧接口 section[] 中的 PV、SP、OVA 等，每组在 HTML 中为一个单独的线路
+    sections: Vec<DetailSection>,
+}
+
+#[derive(Serialize)]
+struct DetailDub {
+    season_id: i64,
+    /// 中配 / 粤配 / 日配 / 原版
+    name: String,
+    sources: Vec<DetailSourceItem>,
+}
+
+#[derive(Serialize)]
+struct DetailSection {
+    name: String,
+    sources: Vec<DetailSourceItem>,
 }
 
 #[get("/search")]
@@ -73,12 +117,43 @@ async fn search_endpoint(
         .get("f")
         .map(|v| v.eq_ignore_ascii_case("html"))
         .unwrap_or(false);
-    match do_search(&data.client, keyword.unwrap(), &data.public_base, html_mode).await {
-        Ok(items) => HttpResponse::Ok().json(ApiResult {
-            code: 0,
-            success: true,
-            message: String::new(),
-            data: items,
+    // video=1 时在第一页的 videos 字段附带普通视频结果，不计入分页
+    let include_video = q
+        .get("video")
+        .is_some_and(|v| v == "1" || v.eq_ignore_ascii_case("true"));
+    let opts = match SearchOptions::from_query(&q) {
+        Ok(o) => o,
+        Err(msg) => {
+            return HttpResponse::BadRequest().json(ApiResult {
+                code: 400,
+                success: false,
+                message: msg,
+                data: Vec::<SearchItem>::new(),
+            });
+        }
+    };
+    match do_search(
+        &data.client,
+        keyword.unwrap(),
+        &data.public_base,
+        html_mode,
+        include_video,
+        &opts,
+    )
+    .await
+    {
+        Ok(page) => HttpResponse::Ok().json(SearchResponse {
+            result: ApiResult {
+                code: 0,
+                success: true,
+                message: String::new(),
+                data: page.items,
+            },
+            page: opts.page,
+            page_size: opts.page_size,
+            total: page.total,
+            has_more: page.has_more,
+            videos: page.videos,
         }),
         Err(e) => {
             log::error!("search error: {e:?}");
@@ -114,54 +189,349 @@ fn map_error_code(err: &anyhow::Error) -> (i32, String) {
     (500, s.lines().next().unwrap_or("内部错误").to_string())
 }
 
+fn error_status(code: i32) -> actix_web::http::StatusCode {
+    if code == -412 {
+        actix_web::http::StatusCode::PRECONDITION_FAILED
+    } else if code == 404 {
+        actix_web::http::StatusCode::NOT_FOUND
+    } else {
+        actix_web::http::StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+/// `/search` 的分页与筛选参数
+struct SearchOptions {
+    /// 从 1 开始
+    page: usize,
+    page_size: usize,
+    /// 最多返回的结果总数，0 为不限
+    limit: usize,
+    year: Option<String>,
+    /// 完结 / 连载
+    status: Option<&'static str>,
+    /// TV / 剧场版 / OVA
+    format: Option<&'static str>,
+    /// 原样传给上游的排序方式
+    order: Option<String>,
+    /// 要搜索的分类，默认番剧与影视都搜
+    media_types: Vec<search::MediaType>,
+}
+
+const DEFAULT_PAGE_SIZE: usize = 20;
+const MAX_PAGE_SIZE: usize = 50;
+/// 为凑满一页而连续翻阅的上游页数上限
+const MAX_UPSTREAM_PAGES: u32 = 100;
+/// 上游分类搜索每页的条数
+const UPSTREAM_PAGE_SIZE: usize = 20;
+
+impl SearchOptions {
+    fn from_query(q: &std::collections::HashMap<String, String>) -> Result<Self, String> {
+        let get = |key: &str| q.get(key).map(|v| v.trim()).filter(|v| !v.is_empty());
+        let number = |key: &str, default: usize| match get(key) {
+            None => Ok(default),
+            Some(v) => v
+                .parse::<usize>()
+                .map_err(|_| format!("{} 参数应为非负整数", key)),
+        };
+        let page = number("page", 1)?.max(1);
+        let page_size = number("page_size", DEFAULT_PAGE_SIZE)?;
+        if page_size == 0 || page_size > MAX_PAGE_SIZE {
+            return Err(format!("page_size 应在 1-{} 之间", MAX_PAGE_SIZE));
+        }
+        let media_types = match get("category") {
+            None => search::MediaType::ALL.to_vec(),
+            Some(c) => vec![search::MediaType::parse(c).ok_or("category 参数应为 bangumi 或 ft")?],
+        };
+        // 超出上游最多能翻到的结果数时不可能有数据，也避免 offset 溢出
+        let reachable = MAX_UPSTREAM_PAGES as usize * UPSTREAM_PAGE_SIZE * media_types.len();
+        if page.checked_mul(page_size).is_none_or(|n| n > reachable) {
+            return Err("page 超出范围".into());
+        }
+        let year = match get("year") {
+            Some(y) if y.len() == 4 && y.chars().all(|c| c.is_ascii_digit()) => Some(y.to_string()),
+            Some(_) => return Err("year 参数应为四位年份".into()),
+            None => None,
+        };
+        let status = match get("status").map(str::to_ascii_lowercase).as_deref() {
+            None => None,
+            Some("finished") => Some("完结"),
+            Some("airing") => Some("连载"),
+            Some(_) => return Err("status 参数应为 finished 或 airing".into()),
+        };
+        let format = match get("type") {
+            None => None,
+            Some(t) if t.eq_ignore_ascii_case("tv") => Some("TV"),
+            Some(t) if t.eq_ignore_ascii_case("ova") => Some("OVA"),
+            Some("剧场版") => Some("剧场版"),
+            Some(_) => return Err("type 参数应为 TV、剧场版 或 OVA".into()),
+        };
+        let order = match get("order") {
+            Some(o) if o.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') => {
+                Some(o.to_string())
+            }
+            Some(_) => return Err("order 参数格式不正确".into()),
+            None => None,
+        };
+        Ok(Self {
+            page,
+            page_size,
+            limit: number("limit", 0)?,
+            year,
+            status,
+            format,
+            order,
+            media_types,
+        })
+    }
+
+    /// 是否有需要在本地过滤的条件；有时上游的总数不再准确
+    fn has_filters(&self) -> bool {
+        self.year.is_some() || self.status.is_some() || self.format.is_some()
+    }
+
+    fn matches(&self, item: &SearchItem) -> bool {
+        self.year.as_ref().is_none_or(|y| &item.year == y)
+            && self.status.is_none_or(|s| item.status == s)
+            && self.format.is_none_or(|f| format_of(item) == f)
+    }
+}
+
+/// 按标题与类型粗分为 TV / 剧场版 / OVA
+fn format_of(item: &SearchItem) -> &'static str {
+    let title = item.title.to_ascii_uppercase();
+    if item.title.contains("剧场版") || item.type_field == "电影" {
+        "剧场版"
+    } else if title.contains("OVA") || title.contains("OAD") {
+        "OVA"
+    } else {
+        "TV"
+    }
+}
+
+/// 一页搜索结果
+struct SearchPage {
+    items: Vec<SearchItem>,
+    /// 结果总数；本地筛选且未翻完上游时未知
+    total: Option<usize>,
+    has_more: bool,
+    /// 普通视频结果，只在请求了视频的第一页返回
+    videos: Option<Vec<SearchItem>>,
+}
+
+/// 在 ApiResult 之外附带分页信息，data 仍为结果数组
+#[derive(Serialize)]
+struct SearchResponse {
+    #[serde(flatten)]
+    result: ApiResult<Vec<SearchItem>>,
+    page: usize,
+    page_size: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total: Option<usize>,
+    has_more: bool,
+    /// 普通视频结果，独立于番剧的分页
+    #[serde(skip_serializing_if = "Option::is_none")]
+    videos: Option<Vec<SearchItem>>,
+}
+
 async fn do_search(
     client: &Client,
     keyword: &str,
     public_base: &str,
     html_mode: bool,
-) -> Result<Vec<SearchItem>> {
-    let raw = search::search_media_bangumi(client, keyword).await?;
-    // 针对每个 season_id 获取详情（做简单并发限制）
+    include_video: bool,
+    opts: &SearchOptions,
+) -> Result<SearchPage> {
+    let base = public_base.trim_end_matches('/');
+    let offset = (opts.page - 1).saturating_mul(opts.page_size);
+    let mut end = offset.saturating_add(opts.page_size);
+    if opts.limit > 0 {
+        end = end.min(opts.limit);
+    }
+    // 只翻到凑满当前页（再多一条用于判断 has_more）为止
+    let mut matched: Vec<(i64, SearchItem)> = Vec::new();
+    let mut seen: std::collections::HashSet<i64> = std::collections::HashSet::new();
+    // 各分类上游给出的结果总数，缺失时为 None
+    let mut upstream_totals = std::collections::HashMap::new();
+    // 尚有后续页的分类；各分类的同一页并行请求，同一页内番剧排在影视之前
+    let mut active = opts.media_types.clone();
+    let mut upstream_page = 1u32;
+    // 有分类的某页请求失败时其后续结果未知，不能当作已翻完
+    let mut incomplete = false;
+    while matched.len() <= end && !active.is_empty() {
+        let pages = futures::future::join_all(active.iter().map(|&t| {
+            search::search_media(client, t, keyword, upstream_page, opts.order.as_deref())
+        }))
+        .await;
+        let mut next = Vec::new();
+        let mut fresh = Vec::new();
+        let mut first_err = None;
+        for (&t, page) in active.iter().zip(pages) {
+            match page {
+                Ok(page) => {
+                    upstream_totals.insert(t, page.num_results);
+                    let before = fresh.len();
+                    fresh.extend(page.items.into_iter().filter(|r| seen.insert(r.season_id)));
+                    // 上游未给出页数时一直翻到某页不再有新结果
+                    if fresh.len() > before
+                        && page.num_pages.is_none_or(|n| upstream_page < n)
+                        && upstream_page < MAX_UPSTREAM_PAGES
+                    {
+                        next.push(t);
+                    }
+                }
+                // 只有部分分类失败时保留其它分类的结果
+                Err(e) => {
+                    log::warn!("search error category={} err={e:#}", t.category());
+                    first_err.get_or_insert(e);
+                }
+            }
+        }
+        if let Some(e) = first_err {
+            if upstream_totals.is_empty() {
+                return Err(e);
+            }
+            incomplete = true;
+        }
+        let items = search_items(client, fresh, base, html_mode).await;
+        matched.extend(items.into_iter().filter(|(_, item)| opts.matches(item)));
+        matched = collapse_dubs(matched);
+        active = next;
+        upstream_page += 1;
+    }
+    let exhausted = active.is_empty() && !incomplete;
+    let upstream_total: Option<usize> = upstream_totals.values().copied().sum();
+    let has_more = matched.len() > end && (opts.limit == 0 || end < opts.limit);
+    let total = if exhausted {
+        Some(matched.len())
+    } else if opts.has_filters() || incomplete {
+        None
+    } else {
+        upstream_total
+    }
+    .map(|t| if opts.limit > 0 { t.min(opts.limit) } else { t });
+    let items: Vec<SearchItem> = matched
+        .into_iter()
+        .skip(offset)
+        .take(end.saturating_sub(offset))
+        .map(|(_, item)| item)
+        .collect();
+    // 普通视频不计入番剧的分页，只随第一页返回一次
+    let videos = if include_video && opts.page == 1 {
+        Some(match search::search_video(client, keyword).await {
+            Ok(videos) => videos
+                .into_iter()
+                .map(|v| SearchItem {
+                    url: if html_mode {
+                        format!("{}/ugc/html/{}", base, v.bvid)
+                    } else {
+                        format!("{}/ugc/detail/{}", base, v.bvid)
+                    },
+                    id: v.bvid,
+                    title: v.title,
+                    cover: v.pic,
+                    description: v.description,
+                    year: ugc::year_of(v.pubdate),
+                    status: String::new(),
+                    type_field: "视频".to_string(),
+                    category: "视频".to_string(),
+                })
+                .collect(),
+            // 视频搜索失败不影响番剧结果
+            Err(e) => {
+                log::warn!("video search error keyword={} err={e:#}", keyword);
+                Vec::new()
+            }
+        })
+    } else {
+        None
+    };
+    Ok(SearchPage {
+        items,
+        total,
+        has_more,
+        videos,
+    })
+}
+
+/// 由一页搜索结果构造条目：字段直接取自搜索接口，只有缺失时才查番剧接口补全；
+/// buffered 保持上游的相关度排序
+async fn search_items(
+    client: &Client,
+    raw: Vec<search::MediaItem>,
+    base: &str,
+    html_mode: bool,
+) -> Vec<(i64, SearchItem)> {
     use futures::stream::{self, StreamExt};
     const CONCURRENCY: usize = 5;
-    let items: Vec<SearchItem> = stream::iter(raw.into_iter())
+    stream::iter(raw)
         .map(|r| async move {
             let id = r.season_id;
-            let detail = fetch_season_detail(client, id).await;
-            let (cover, desc, year, status, type_name) = match detail {
-                Ok(d) => d,
-                Err(e) => {
-                    eprintln!("season detail error season_id={} err={}", id, e);
-                    (
-                        String::new(),
-                        String::new(),
-                        String::new(),
-                        String::new(),
-                        String::from("TV"),
-                    )
-                }
-            };
-            let base = public_base.trim_end_matches('/');
-            let url = if html_mode {
-                format!("{}/html/{}", base, id)
-            } else {
-                format!("{}/detail/{}", base, id)
-            };
-            SearchItem {
+            let mut item = SearchItem {
                 id: id.to_string(),
                 title: r.title,
-                cover,
-                description: desc,
-                year,
-                status,
-                type_field: type_name,
-                url,
+                cover: r.cover.unwrap_or_default(),
+                description: r.desc.unwrap_or_default(),
+                year: r
+                    .pub_time
+                    .map(|t| t.chars().take(4).collect())
+                    .unwrap_or_default(),
+                status: r
+                    .is_finish
+                    .map(|f| if f { "完结" } else { "连载" }.to_string())
+                    .unwrap_or_default(),
+                type_field: r.season_type_name.unwrap_or_default(),
+                category: r.media_type.category().to_string(),
+                url: if html_mode {
+                    format!("{}/html/{}", base, id)
+                } else {
+                    format!("{}/detail/{}", base, id)
+                },
+            };
+            let missing = [
+                &item.cover,
+                &item.description,
+                &item.year,
+                &item.status,
+                &item.type_field,
+            ]
+            .iter()
+            .any(|f| f.is_empty());
+            if missing {
+                match season_summary(client, id).await {
+                    Ok(s) => item.fill_missing(s),
+                    Err(e) => log::warn!("season summary error season_id={} err={e:#}", id),
+                }
+            }
+            if item.type_field.is_empty() {
+                item.type_field = "TV".to_string();
             }
+            (id, item)
         })
-        .buffer_unordered(CONCURRENCY)
+        .buffered(CONCURRENCY)
         .collect()
-        .await;
-    Ok(items)
+        .await
+}
+
+/// 配音版本在详情页中已是同一标题下的线路，搜索结果中去掉配音标记后同名的条目只保留一个：
+/// 有原版时保留原版，否则保留最先出现的配音版，放在这部作品最先出现的位置。
+/// 追加后续页只会替换或去掉条目，不会改变已有条目的数量，分页的 offset 保持稳定
+fn collapse_dubs(items: Vec<(i64, SearchItem)>) -> Vec<(i64, SearchItem)> {
+    let mut slots: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
+    let mut out: Vec<(i64, SearchItem)> = Vec::new();
+    for (id, item) in items {
+        let dubbed = dub::is_dub(&item.title);
+        let base = dub::base_title(&item.title);
+        match slots.get(&base).copied() {
+            Some(_) if dubbed => {}
+            // 原版替换先出现的配音版
+            Some(slot) if dub::is_dub(&out[slot].1.title) => out[slot] = (id, item),
+            _ => {
+                slots.entry(base).or_insert(out.len());
+                out.push((id, item));
+            }
+        }
+    }
+    out
 }
 
 #[get("/detail/{id}")]
@@ -188,14 +558,7 @@ async fn detail_endpoint(path: web::Path<(String,)>, data: web::Data<AppState>)
         Err(e) => {
             log::error!("detail error id={} err={e:#}", season_id);
             let (code, msg) = map_error_code(&e);
-            let status_code = if code == -412 {
-                actix_web::http::StatusCode::PRECONDITION_FAILED
-            } else if code == 404 {
-                actix_web::http::StatusCode::NOT_FOUND
-            } else {
-                actix_web::http::StatusCode::INTERNAL_SERVER_ERROR
-            };
-            HttpResponse::build(status_code).json(ApiResult {
+            HttpResponse::build(error_status(code)).json(ApiResult {
                 code,
                 success: false,
                 message: msg,
@@ -223,33 +586,106 @@ async fn html_endpoint(
     // 复用 fetch_season_full 获取 episodes
     match fetch_season_full(&data.client, season_id, &data.public_base).await {
         Ok(detail) => {
-            // 仅保留现有线路，命名为 “星源通道”
-            let base_host = req.connection_info().host().to_string();
-            let scheme = if req.connection_info().scheme() == "https" {
-                "https"
-            } else {
-                "http"
-            };
-            let line_base = if data.public_base.starts_with("http://")
-                || data.public_base.starts_with("https://")
-            {
-                data.public_base.trim_end_matches('/').to_string()
-            } else {
-                format!("{}://{}", scheme, base_host)
-            };
-            let mut panel = String::new();
-            for s in &detail.sources {
-                let rel = format!("/hls/{}/{}/index.m3u8", detail.id, s.sort);
-                let name_escaped = html_escape(&s.name);
-                panel.push_str(&format!(
-                    "      <a href=\"{base}{rel}\">{name}</a>\n",
-                    base = line_base,
-                    rel = rel,
-                    name = name_escaped
-                ));
-            }
-            let page = format!(
-                r#"<!DOCTYPE html>
+            let base = request_base(&data.public_base, &req);
+            let page = render_detail_html(&detail, &base);
+            HttpResponse::Ok()
+                .insert_header(("Content-Type", "text/html; charset=utf-8"))
+                .body(page)
+        }
+        Err(e) => {
+            log::error!("html detail error id={} err={e:#}", season_id);
+            let (code, msg) = map_error_code(&e);
+            HttpResponse::build(error_status(code))
+                .insert_header(("Content-Type", "text/plain; charset=utf-8"))
+                .body(format!("获取剧集失败: {}", msg))
+        }
+    }
+}
+
+/// 对外可见的 base 地址：public_base 为完整地址时直接使用，否则按请求的 scheme 与 host 推断
+fn request_base(public_base: &str, req: &HttpRequest) -> String {
+    if public_base.starts_with("http://") || public_base.starts_with("https://") {
+        public_base.trim_end_matches('/').to_string()
+    } else {
+        let host = req.connection_info().host().to_string();
+        let scheme = if req.connection_info().scheme() == "https" {
+            "https"
+        } else {
+            "http"
+        };
+        format!("{}://{}", scheme, host)
+    }
+}
+
+/// 管理接口鉴权：要求 `Authorization: Bearer <[api] admin_token>`。
+/// 未配置令牌时一律拒绝（反向代理后的请求都来自本机，无法按来源判断）。未通过时返回 403 响应
+pub(crate) fn admin_guard(req: &HttpRequest) -> Option<HttpResponse> {
+    let token = &config::get().api.admin_token;
+    let message = if token.is_empty() {
+        "管理接口未启用，请先设置 [api] admin_token"
+    } else if req
+        .headers()
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        != Some(token.as_str())
+    {
+        "无权访问管理接口"
+    } else {
+        return None;
+    };
+    Some(HttpResponse::Forbidden().json(ApiResult {
+        code: 403,
+        success: false,
+        message: message.to_string(),
+        data: (),
+    }))
+}
+
+/// 剧集列表页：每集链接到 `{base}{path}`。正片为“星源通道”，
+/// 其后依次是各配音版本与 section 中的 PV、SP 等，各为一个线路，`.channel-tabs` 与 `.episode-panels` 按顺序一一对应
+fn render_detail_html(detail: &DetailData, base: &str) -> String {
+    let channels = std::iter::once(("星源通道", detail.sources.as_slice()))
+        .chain(
+            detail
+                .dubs
+                .iter()
+                .map(|d| (d.name.as_str(), d.sources.as_slice())),
+        )
+        .chain(
+            detail
+                .sections
+                .iter()
+                .map(|s| (s.name.as_str(), s.sources.as_slice())),
+        );
+    render_channels_html(&detail.title, channels, base)
+}
+
+/// Animeko 可解析的条目页：每个线路一个 `.channel-tabs a` 与一个 `.episode-panels > div`
+fn render_channels_html<'a>(
+    title: &str,
+    channels: impl Iterator<Item = (&'a str, &'a [DetailSourceItem])>,
+    base: &str,
+) -> String {
+    let mut tabs = String::new();
+    let mut panels = String::new();
+    for (channel, sources) in channels {
+        tabs.push_str(&format!("    <a>{}</a>\n", html_escape(channel)));
+        let mut panel = String::new();
+        for s in sources {
+            let rel = &s.path;
+            let name_escaped = html_escape(&s.name);
+            panel.push_str(&format!(
+                "      <a href=\"{base}{rel}\">{name}</a>\n",
+                base = base,
+                rel = rel,
+                name = name_escaped
+            ));
+        }
+        panels.push_str(&format!("    <div class=\"panel\">\n{}    </div>\n", panel));
+    }
+    format!(
+        r#"<!DOCTYPE html>
 <html lang="zh-CN">
 <head>
   <meta charset="utf-8" />
@@ -257,40 +693,18 @@ async fn html_endpoint(
 </head>
 <body>
   <h1>{title}</h1>
-  <!-- 单一路线标签 -->
+  <!-- 线路标签 -->
   <div class="channel-tabs">
-    <a>星源通道</a>
-  </div>
+{tabs}  </div>
   <!-- 剧集列表 -->
   <div class="episode-panels">
-    <div class="panel">
-{panel}    
-</div>
-  </div>
+{panels}  </div>
 </body>
 </html>"#,
-                title = html_escape(&detail.title),
-                panel = panel,
-            );
-            HttpResponse::Ok()
-                .insert_header(("Content-Type", "text/html; charset=utf-8"))
-                .body(page)
-        }
-        Err(e) => {
-            log::error!("html detail error id={} err={e:#}", season_id);
-            let (code, msg) = map_error_code(&e);
-            let status_code = if code == -412 {
-                actix_web::http::StatusCode::PRECONDITION_FAILED
-            } else if code == 404 {
-                actix_web::http::StatusCode::NOT_FOUND
-            } else {
-                actix_web::http::StatusCode::INTERNAL_SERVER_ERROR
-            };
-            HttpResponse::build(status_code)
-                .insert_header(("Content-Type", "text/plain; charset=utf-8"))
-                .body(format!("获取剧集失败: {}", msg))
-        }
-    }
+        title = html_escape(title),
+        tabs = tabs,
+        panels = panels,
+    )
 }
 
 // 简单 HTML 转义（最少需求）
@@ -307,13 +721,7 @@ const PROVIDE_JSON_TEXT: &str = include_str!("provide.json");
 #[get("/")]
 async fn provide_endpoint(data: web::Data<AppState>, req: HttpRequest) -> impl Responder {
     // 计算对外可见的 base 地址
-    let base = if data.public_base.starts_with("http://") || data.public_base.starts_with("https://") {
-        data.public_base.trim_end_matches('/').to_string()
-    } else {
-        let host = req.connection_info().host().to_string();
-        let scheme = if req.connection_info().scheme() == "https" { "https" } else { "http" };
-        format!("{}://{}", scheme, host)
-    };
+    let base = request_base(&data.public_base, &req);
     // 硬字符替代占位符
     let body = PROVIDE_JSON_TEXT.replace("[config.api.public_base]", &base);
     HttpResponse::Ok()
@@ -321,68 +729,43 @@ async fn provide_endpoint(data: web::Data<AppState>, req: HttpRequest) -> impl R
         .body(body)
 }
 
-async fn fetch_season_detail(
-    client: &Client,
-    season_id: i64,
-) -> Result<(String, String, String, String, String)> {
-    let mut url = reqwest::Url::parse("https://api.bilibili.com/pgc/view/web/season")?;
-    url.query_pairs_mut()
-        .append_pair("season_id", &season_id.to_string());
-    let resp = client
-        .get(url)
-        .header("Referer", "https://www.bilibili.com")
-        .send()
-        .await?;
-    let status = resp.status();
-    let text = resp.text().await?;
-    let v: Value = serde_json::from_str(&text).map_err(|e| {
-        anyhow::anyhow!(
-            "season detail parse fail id={} status={} err={} body_snip={}",
-            season_id,
-            status,
-            e,
-            &text.chars().take(120).collect::<String>()
-        )
-    })?;
-    let root = v
-        .get("result")
-        .or_else(|| v.get("data"))
-        .cloned()
-        .unwrap_or(Value::Null);
-    let cover = root
-        .get("cover")
-        .or_else(|| root.get("season_cover"))
-        .and_then(|v| v.as_str())
-        .unwrap_or("")
-        .to_string();
-    let desc = root
-        .get("evaluate")
-        .and_then(|v| v.as_str())
-        .unwrap_or("")
-        .to_string();
-    let publish_time = root
-        .get("publish")
-        .and_then(|p| p.get("pub_time"))
-        .and_then(|s| s.as_str())
-        .unwrap_or("");
-    let year = if publish_time.len() >= 4 {
-        publish_time[..4].to_string()
-    } else {
-        String::new()
-    };
-    let is_finish = root
-        .get("publish")
-        .and_then(|p| p.get("is_finish"))
-        .and_then(|b| b.as_i64())
-        .unwrap_or(0)
-        == 1;
-    let status = if is_finish { "完结" } else { "连载" }.to_string();
-    let type_name = root
-        .get("season_type_name")
-        .and_then(|v| v.as_str())
-        .unwrap_or("TV")
-        .to_string();
-    Ok((cover, desc, year, status, type_name))
+/// 搜索结果补全用的番剧概要
+#[derive(Clone)]
+struct SeasonSummary {
+    cover: String,
+    description: String,
+    year: String,
+    status: String,
+    type_name: String,
+}
+
+impl SearchItem {
+    /// 只填充搜索结果中缺失的字段
+    fn fill_missing(&mut self, s: SeasonSummary) {
+        for (field, value) in [
+            (&mut self.cover, s.cover),
+            (&mut self.description, s.description),
+            (&mut self.year, s.year),
+            (&mut self.status, s.status),
+            (&mut self.type_field, s.type_name),
+        ] {
+            if field.is_empty() {
+                *field = value;
+            }
+        }
+    }
+}
+
+/// 番剧概要，经由 season 服务的缓存，在各次搜索间共享
+async fn season_summary(client: &Client, season_id: i64) -> Result<SeasonSummary> {
+    let season = season::get(client, season_id).await?;
+    Ok(SeasonSummary {
+        cover: season.cover.clone(),
+        description: season.evaluate.clone(),
+        year: season.year(),
+        status: season.status().to_string(),
+        type_name: season.type_name().to_string(),
+    })
 }
 
 async fn fetch_season_full(
@@ -390,86 +773,65 @@ async fn fetch_season_full(
     season_id: i64,
     public_base: &str,
 ) -> Result<DetailData> {
-    let mut url = reqwest::Url::parse("https://api.bilibili.com/pgc/view/web/season")?;
-    url.query_pairs_mut()
-        .append_pair("season_id", &season_id.to_string());
-    let resp = client
-        .get(url)
-        .header("Referer", "https://www.bilibili.com")
-        .send()
-        .await?;
-    let status = resp.status();
-    let text = resp.text().await?;
-    let v: Value = serde_json::from_str(&text).map_err(|e| {
-        anyhow::anyhow!(
-            "season detail parse fail id={} status={} err={} body_snip={}",
-            season_id,
-            status,
-            e,
-            &text.chars().take(160).collect::<String>()
-        )
-    })?;
-    // 统一 root: 有的返回 result，有的返回 data
-    let root = v
-        .get("result")
-        .or_else(|| v.get("data"))
-        .cloned()
-        .unwrap_or(Value::Null);
-    if root.is_null() {
-        return Err(anyhow::anyhow!("season not found id={}", season_id));
-    }
-    let title = root
-        .get("title")
-        .or_else(|| root.get("season_title"))
-        .and_then(|v| v.as_str())
-        .unwrap_or("")
-        .to_string();
+    let season = season::get(client, season_id).await?;
+    let mut detail = season_detail_of(&season, public_base)?;
+    // 仅配音不同的兄弟 season（中配、粤配等）作为额外线路，获取失败的跳过
+    detail.dubs =
+        futures::future::join_all(dub::siblings(&season).into_iter().map(|sib| async move {
+            match season::get(client, sib.season_id).await {
+                Ok(s) => Some(DetailDub {
+                    season_id: sib.season_id,
+                    name: sib.label.to_string(),
+                    sources: episode_sources(&s, public_base),
+                }),
+                Err(e) => {
+                    log::warn!("获取配音版本失败 season_id={} err={e:#}", sib.season_id);
+                    None
+                }
+            }
+        }))
+        .await
+        .into_iter()
+        .flatten()
+        .filter(|d| !d.sources.is_empty())
+        .collect();
+    Ok(detail)
+}
+
+/// 番剧的元数据、正片与 section（不含配音版本）
+fn season_detail_of(season: &Season, public_base: &str) -> Result<DetailData> {
+    let title = season.display_title().to_string();
     if title.is_empty() {
-        return Err(anyhow::anyhow!("title empty id={}", season_id));
+        return Err(anyhow::anyhow!("title empty id={}", season.season_id));
     }
-    let cover = root
-        .get("cover")
-        .or_else(|| root.get("season_cover"))
-        .and_then(|v| v.as_str())
-        .unwrap_or("")
-        .to_string();
-    let desc = root
-        .get("evaluate")
-        .and_then(|v| v.as_str())
-        .unwrap_or("")
-        .to_string();
-    let publish_time = root
-        .get("publish")
-        .and_then(|p| p.get("pub_time"))
-        .and_then(|s| s.as_str())
-        .unwrap_or("");
-    let year = if publish_time.len() >= 4 {
-        publish_time[..4].to_string()
-    } else {
-        String::new()
-    };
-    let is_finish = root
-        .get("publish")
-        .and_then(|p| p.get("is_finish"))
-        .and_then(|b| b.as_i64())
-        .unwrap_or(0)
-        == 1;
-    let status = if is_finish { "完结" } else { "连载" }.to_string();
-    let type_name = root
-        .get("season_type_name")
-        .and_then(|v| v.as_str())
-        .unwrap_or("TV")
-        .to_string();
-    let eps_arr = root
-        .get("episodes")
-        .and_then(|v| v.as_array())
-        .cloned()
-        .unwrap_or_default();
+    let sections = season
+        .section
+        .iter()
+        .filter_map(|sec| section_of(sec, public_base))
+        .collect();
+    Ok(DetailData {
+        id: season.season_id.to_string(),
+        title,
+        cover: season.cover.clone(),
+        description: season.evaluate.clone(),
+        year: season.year(),
+        status: season.status().to_string(),
+        type_field: season.type_name().to_string(),
+        sources: episode_sources(season, public_base),
+        dubs: Vec::new(),
+        sections,
+    })
+}
+
+/// 正片 episodes[] 转为播放源
+fn episode_sources(season: &Season, public_base: &str) -> Vec<DetailSourceItem> {
+    let season_id = season.season_id;
+    let eps_arr = &season.episodes;
     let mut sources: Vec<DetailSourceItem> = Vec::with_capacity(eps_arr.len());
     for (idx, ep) in eps_arr.iter().enumerate() {
         let ep_index = idx + 1; // 1-based
-        let ep_title_num = ep.get("title").and_then(|v| v.as_str()).unwrap_or("");
-        let ep_long = ep.get("long_title").and_then(|v| v.as_str()).unwrap_or("");
+        let ep_title_num = ep.title.as_str();
+        let ep_long = ep.long_title.as_str();
         let name = if ep_long.is_empty() {
             if ep_title_num.is_empty() {
                 format!("第{}集", ep_index)
@@ -483,32 +845,90 @@ async fn fetch_season_full(
                 format!("第{}集 {}", ep_title_num, ep_long)
             }
         };
-        sources.push(DetailSourceItem {
+        // 有 ep_id 时按 ep_id 寻址，上游插入或调整剧集后链接仍指向同一集
+        let fallback = format!("/hls/{}/{}/index.m3u8", season_id, ep_index);
+        sources.extend(episode_source(
+            ep,
             name,
-            sort: ep_index,
-            m3u8: format!(
-                "{}/hls/{}/{}/index.m3u8",
-                public_base.trim_end_matches('/'),
-                season_id,
-                ep_index
-            ),
-        });
+            ep_index,
+            Some(fallback),
+            public_base,
+        ));
     }
-    Ok(DetailData {
-        id: season_id.to_string(),
-        title,
-        cover,
-        description: desc,
-        year,
-        status,
-        type_field: type_name,
-        sources,
+    sources
+}
+
+/// 单集条目转为播放源；没有 ep_id 时使用 `fallback` 路径，两者都没有则跳过
+fn episode_source(
+    ep: &Episode,
+    name: String,
+    sort: usize,
+    fallback: Option<String>,
+    public_base: &str,
+) -> Option<DetailSourceItem> {
+    let ep_id = ep.ep_id();
+    let path = match ep_id {
+        Some(ep_id) => format!("/hls/ep/{}/index.m3u8", ep_id),
+        None => fallback?,
+    };
+    Some(DetailSourceItem {
+        name,
+        sort,
+        ep_id,
+        aid: ep.aid,
+        cid: ep.cid,
+        bvid: ep.bvid.clone(),
+        m3u8: format!("{}{}", public_base.trim_end_matches('/'), path),
+        path,
+        skip: hls::EpisodeSkip::from_episode(ep),
     })
 }
 
+/// section[] 中的一组（PV、特别篇等）；其中的剧集不在 episodes 里，只能按 ep_id 播放
+fn section_of(sec: &Section, public_base: &str) -> Option<DetailSection> {
+    let name = if sec.title.is_empty() {
+        "花絮".to_string()
+    } else {
+        sec.title.clone()
+    };
+    let sources: Vec<DetailSourceItem> = sec
+        .episodes
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, ep)| {
+            let (title, long_title) = (ep.title.as_str(), ep.long_title.as_str());
+            let name = match (title.is_empty(), long_title.is_empty()) {
+                (false, false) => format!("{} {}", title, long_title),
+                (false, true) => title.to_string(),
+                (true, false) => long_title.to_string(),
+                (true, true) => format!("{} {}", name, idx + 1),
+            };
+            episode_source(ep, name, idx + 1, None, public_base)
+        })
+        .collect();
+    (!sources.is_empty()).then_some(DetailSection { name, sources })
+}
+
+/// 手动淘汰缓存：`selfani cache gc`
+fn run_cache_gc() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    // 独立进程看不到服务内的任务表，仅凭 .lock 判断目录是否在写入
+    let report = cache::gc(&Default::default());
+    println!(
+        "扫描 {} 个目录，删除 {} 个，跳过 {} 个，释放 {} 字节，剩余 {} 字节",
+        report.scanned, report.removed, report.skipped, report.freed_bytes, report.total_bytes
+    );
+}
+
 #[actix_web::main]
 async fn main() -> anyhow::Result<()> {
     let cfg = config::get();
+    // 命令行子命令：selfani cache gc（手动淘汰缓存后退出，不启动服务）；其它参数忽略
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args == ["cache", "gc"] {
+        run_cache_gc();
+        return Ok(());
+    }
     let bind_addr = cfg.api.bind.clone();
     let public_base = cfg.api.public_base.clone();
     // 载入（或创建空） cookie store
@@ -542,7 +962,20 @@ async fn main() -> anyhow::Result<()> {
         env_logger::Env::default().default_filter_or("info,actix_web=info,selfani=info"),
     )
     .init();
+    if !args.is_empty() {
+        log::warn!(
+            "忽略未知的命令行参数: {}（可用子命令: cache gc）",
+            args.join(" ")
+        );
+    }
     log::info!("Starting server at http://{}", bind_addr);
+    if cfg.api.admin_token.is_empty() {
+        log::warn!("未设置 [api] admin_token，/jobs 与 /admin/* 管理接口已禁用");
+    }
+    cache::recover_on_startup();
+    season::restore_on_startup();
+    supervisor::spawn_reaper();
+    cache::spawn_gc();
     HttpServer::new(move || {
         App::new()
             .wrap(ActixLogger::default())
@@ -563,7 +996,19 @@ async fn main() -> anyhow::Result<()> {
             .service(detail_endpoint)
             .service(html_endpoint)
             .service(provide_endpoint)
+            .service(series::series_endpoint)
+            .service(ugc::ugc_detail_endpoint)
+            .service(ugc::ugc_html_endpoint)
+            .service(supervisor::jobs_endpoint)
+            .service(cache::cache_gc_endpoint)
+            .service(danmaku::danmaku_endpoint)
+            .service(subtitle::subtitle_playlist)
+            .service(subtitle::subtitle_vtt)
+            .service(remux::remux_track_playlist)
+            .service(remux::remux_media)
+            .service(hls::hls_master)
             .service(hls::hls_playlist)
+            .service(hls::hls_variant_playlist)
             .service(hls::hls_segment)
     })
     .bind(bind_addr)?
@@ -571,3 +1016,86 @@ async fn main() -> anyhow::Result<()> {
     .await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: i64, title: &str) -> (i64, SearchItem) {
+        (
+            id,
+            SearchItem {
+                id: id.to_string(),
+                title: title.to_string(),
+                cover: String::new(),
+                description: String::new(),
+                year: String::new(),
+                status: String::new(),
+                type_field: String::new(),
+                category: String::new(),
+                url: String::new(),
+            },
+        )
+    }
+
+    fn ids(items: &[(i64, SearchItem)]) -> Vec<i64> {
+        items.iter().map(|(id, _)| *id).collect()
+    }
+
+    #[test]
+    fn collapse_dubs_keeps_original() {
+        let items = vec![
+            item(45969, "葬送的芙莉莲"),
+            item(47040, "葬送的芙莉莲（中配）"),
+            item(28770, "间谍过家家 第二季"),
+        ];
+        assert_eq!(ids(&collapse_dubs(items)), [45969, 28770]);
+    }
+
+    #[test]
+    fn collapse_dubs_moves_original_into_first_slot() {
+        // 配音版排在原版之前时原版占据配音版的位置，追加的条目不会改变前面的条目数
+        let head = || {
+            vec![
+                item(47040, "葬送的芙莉莲（中配）"),
+                item(28770, "间谍过家家 第二季"),
+            ]
+        };
+        assert_eq!(ids(&collapse_dubs(head())), [47040, 28770]);
+        let mut items = head();
+        items.push(item(45969, "葬送的芙莉莲"));
+        items.push(item(47041, "葬送的芙莉莲 粤配"));
+        let all = collapse_dubs(items);
+        assert_eq!(ids(&all), [45969, 28770]);
+        assert_eq!(ids(&collapse_dubs(all)), [45969, 28770]);
+    }
+
+    #[test]
+    fn collapse_dubs_keeps_one_dub_without_original() {
+        let items = vec![
+            item(41410, "名侦探柯南 粤配"),
+            item(33415, "名侦探柯南（中配）"),
+            item(5978, "跟着日语去旅行"),
+        ];
+        assert_eq!(ids(&collapse_dubs(items)), [41410, 5978]);
+    }
+
+    #[test]
+    fn collapse_dubs_ignores_plain_language_words() {
+        let items = vec![item(1, "普通话水平测试精讲"), item(2, "航海王（原声版）")];
+        assert_eq!(ids(&collapse_dubs(items)), [1, 2]);
+    }
+
+    #[test]
+    fn search_page_out_of_range() {
+        let query = |page: &str| {
+            let q = [("page".to_string(), page.to_string())]
+                .into_iter()
+                .collect();
+            SearchOptions::from_query(&q).map(|o| o.page)
+        };
+        assert_eq!(query("200"), Ok(200));
+        assert!(query("201").is_err());
+        assert!(query(&usize::MAX.to_string()).is_err());
+    }
+}
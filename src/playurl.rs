@@ -13,6 +13,15 @@ pub struct PlayurlResp {
 #[derive(Debug, Deserialize)]
 pub struct PlayurlData {
     pub dash: Option<PlayurlDash>,
+    /// 未返回 dash 时的 FLV/MP4 分段
+    #[serde(default)]
+    pub durl: Option<Vec<DurlSegment>>,
+    #[serde(default)]
+    pub quality: i32,
+    #[serde(default)]
+    pub format: String,
+    #[serde(default)]
+    pub timelength: u64,
 }
 
 #[allow(dead_code)]
@@ -22,9 +31,12 @@ pub struct PlayVideo {
     pub base_url: String,
     pub backup_url: Option<Vec<String>>,
     pub codecid: Option<i32>,
+    pub codecs: Option<String>,
     pub width: Option<u32>,
     pub height: Option<u32>,
+    pub frame_rate: Option<String>,
     pub bandwidth: Option<u64>,
+    pub segment_base: Option<SegmentBase>,
 }
 
 #[allow(dead_code)]
@@ -35,6 +47,33 @@ pub struct PlayAudio {
     pub backup_url: Option<Vec<String>>,
     pub bandwidth: Option<u64>,
     pub codecs: Option<String>,
+    pub segment_base: Option<SegmentBase>,
+}
+
+/// DASH SegmentBase：单文件 fMP4 中 init（ftyp+moov）与 sidx 的字节范围，形如 "0-1000"
+#[derive(Debug, Clone)]
+pub struct SegmentBase {
+    pub initialization: String,
+    pub index_range: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SegmentBaseWire {
+    #[serde(rename = "Initialization")]
+    initialization_camel: Option<String>,
+    initialization: Option<String>,
+    #[serde(rename = "indexRange")]
+    index_range_camel: Option<String>,
+    index_range: Option<String>,
+}
+
+impl SegmentBaseWire {
+    fn into_segment_base(self) -> Option<SegmentBase> {
+        Some(SegmentBase {
+            initialization: self.initialization.or(self.initialization_camel)?,
+            index_range: self.index_range.or(self.index_range_camel)?,
+        })
+    }
 }
 
 // 中间结构：同时接收 camelCase 与 snake_case，避免 alias 导致的 duplicate field 错误
@@ -50,11 +89,19 @@ struct PlayVideoWire {
     #[serde(default)]
     codecid: Option<i32>,
     #[serde(default)]
+    codecs: Option<String>,
+    #[serde(default)]
     width: Option<u32>,
     #[serde(default)]
     height: Option<u32>,
+    #[serde(rename = "frameRate")]
+    frame_rate_camel: Option<String>,
+    frame_rate: Option<String>,
     #[serde(default)]
     bandwidth: Option<u64>,
+    #[serde(rename = "SegmentBase")]
+    segment_base_camel: Option<SegmentBaseWire>,
+    segment_base: Option<SegmentBaseWire>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -70,6 +117,9 @@ struct PlayAudioWire {
     bandwidth: Option<u64>,
     #[serde(default)]
     codecs: Option<String>,
+    #[serde(rename = "SegmentBase")]
+    segment_base_camel: Option<SegmentBaseWire>,
+    segment_base: Option<SegmentBaseWire>,
 }
 
 impl<'de> DeDeserialize<'de> for PlayVideo {
@@ -88,9 +138,15 @@ impl<'de> DeDeserialize<'de> for PlayVideo {
             base_url,
             backup_url,
             codecid: w.codecid,
+            codecs: w.codecs,
             width: w.width,
             height: w.height,
+            frame_rate: w.frame_rate.or(w.frame_rate_camel),
             bandwidth: w.bandwidth,
+            segment_base: w
+                .segment_base
+                .or(w.segment_base_camel)
+                .and_then(SegmentBaseWire::into_segment_base),
         })
     }
 }
@@ -112,6 +168,10 @@ impl<'de> DeDeserialize<'de> for PlayAudio {
             backup_url,
             bandwidth: w.bandwidth,
             codecs: w.codecs,
+            segment_base: w
+                .segment_base
+                .or(w.segment_base_camel)
+                .and_then(SegmentBaseWire::into_segment_base),
         })
     }
 }
@@ -124,8 +184,42 @@ pub struct Dolby {
 pub struct Flac {
     pub audio: PlayAudio,
 }
+/// durl 中的一段：音视频已混流，较长的 FLV 会被切成多段
+#[derive(Debug, Deserialize, Clone)]
+pub struct DurlSegment {
+    #[serde(default)]
+    pub order: u32,
+    /// 时长（毫秒）
+    #[serde(default)]
+    pub length: u64,
+    #[serde(default)]
+    pub size: u64,
+    pub url: String,
+    #[serde(default)]
+    pub backup_url: Option<Vec<String>>,
+}
+
+/// 部分老番与未登录画质只返回 durl（FLV/MP4 分段）而没有 dash
+#[derive(Debug, Deserialize, Clone)]
+pub struct PlayurlDurl {
+    /// 实际画质 qn
+    #[serde(default)]
+    pub quality: i32,
+    /// flv / flv720 / mp4 等
+    #[serde(default)]
+    pub format: String,
+    /// 总时长（毫秒）
+    #[serde(default)]
+    pub timelength: u64,
+    #[serde(rename = "durl")]
+    pub segments: Vec<DurlSegment>,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct PlayurlDash {
+    /// 时长（秒）
+    #[serde(default)]
+    pub duration: Option<u64>,
     #[serde(default)]
     pub video: Vec<PlayVideo>,
     #[serde(default)]
@@ -134,9 +228,25 @@ pub struct PlayurlDash {
     pub dolby: Option<Dolby>,
     #[serde(default)]
     pub flac: Option<Flac>,
+    /// 只有 durl 时为 Some，此时 video / audio 为空
+    #[serde(skip)]
+    pub durl: Option<PlayurlDurl>,
+}
+
+impl PlayurlDash {
+    fn from_durl(mut durl: PlayurlDurl) -> Self {
+        durl.segments.sort_by_key(|s| s.order);
+        Self {
+            duration: Some(durl.timelength.div_ceil(1000)),
+            video: Vec::new(),
+            audio: Vec::new(),
+            dolby: None,
+            flac: None,
+            durl: Some(durl),
+        }
+    }
 }
 
-#[allow(dead_code)]
 pub async fn fetch_dash_ugc(
     client: &Client,
     aid: u64,
@@ -192,7 +302,27 @@ pub async fn fetch_dash_ugc(
         );
         return Err(anyhow!("获取播放地址失败 code={}", v.code));
     }
-    let mut dash = v.data.dash.ok_or_else(|| anyhow!("没有 dash 返回"))?;
+    let mut dash = match v.data.dash {
+        Some(dash) => dash,
+        None => match v.data.durl {
+            Some(segments) if !segments.is_empty() => {
+                log::info!(
+                    "UGC 未返回 dash，使用 durl: aid={} cid={} format={} segments={}",
+                    aid,
+                    cid,
+                    v.data.format,
+                    segments.len()
+                );
+                PlayurlDash::from_durl(PlayurlDurl {
+                    quality: v.data.quality,
+                    format: v.data.format,
+                    timelength: v.data.timelength,
+                    segments,
+                })
+            }
+            _ => return Err(anyhow!("没有 dash 返回")),
+        },
+    };
     if let Some(d) = &dash.dolby {
         if let Some(list) = &d.audio {
             for a in list {
@@ -203,6 +333,7 @@ pub async fn fetch_dash_ugc(
     if let Some(f) = &dash.flac {
         dash.audio.push(f.audio.clone());
     }
+    crate::cdn::rewrite_dash(&mut dash);
     log::debug!(
         "UGC dash parsed: videos={} audios={} dolby={} flac={}",
         dash.video.len(),
@@ -255,17 +386,7 @@ pub async fn fetch_dash_pgc(
         .json()
         .await?;
 
-    let dash_v = v
-        .get("data")
-        .and_then(|d| d.get("dash"))
-        .or_else(|| v.get("result").and_then(|r| r.get("dash")))
-        .or_else(|| {
-            v.get("result")
-                .and_then(|r| r.get("video_info"))
-                .and_then(|vi| vi.get("dash"))
-        })
-        .ok_or_else(|| anyhow!("PGC 未返回 dash"))?;
-    let mut dash: PlayurlDash = serde_json::from_value(dash_v.clone())?;
+    let mut dash = pgc_dash(&v, ep_id)?;
     if let Some(d) = &dash.dolby {
         if let Some(list) = &d.audio {
             for a in list {
@@ -276,6 +397,7 @@ pub async fn fetch_dash_pgc(
     if let Some(f) = &dash.flac {
         dash.audio.push(f.audio.clone());
     }
+    crate::cdn::rewrite_dash(&mut dash);
     log::debug!(
         "PGC dash parsed: videos={} audios={} dolby={} flac={}",
         dash.video.len(),
@@ -289,3 +411,63 @@ pub async fn fetch_dash_pgc(
     );
     Ok(dash)
 }
+
+/// 从 PGC playurl 响应中取出 dash，没有 dash（缺失或为 null）时退回 durl
+fn pgc_dash(v: &serde_json::Value, ep_id: u64) -> Result<PlayurlDash> {
+    // dash / durl 可能位于 data、result 或 result.video_info 下
+    let roots = [
+        v.get("data"),
+        v.get("result"),
+        v.get("result").and_then(|r| r.get("video_info")),
+    ];
+    let dash_v = roots
+        .iter()
+        .flatten()
+        .find_map(|r| r.get("dash").filter(|d| !d.is_null()));
+    let durl_v = roots.iter().flatten().find(|r| {
+        r.get("durl")
+            .and_then(|d| d.as_array())
+            .is_some_and(|d| !d.is_empty())
+    });
+    let dash: PlayurlDash = match (dash_v, durl_v) {
+        (Some(dash_v), _) => serde_json::from_value(dash_v.clone())?,
+        (None, Some(durl_v)) => {
+            let durl: PlayurlDurl = serde_json::from_value((*durl_v).clone())?;
+            log::info!(
+                "PGC 未返回 dash，使用 durl: ep_id={} format={} segments={}",
+                ep_id,
+                durl.format,
+                durl.segments.len()
+            );
+            PlayurlDash::from_durl(durl)
+        }
+        (None, None) => return Err(anyhow!("PGC 未返回 dash")),
+    };
+    Ok(dash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn null_dash_falls_back_to_durl() {
+        let v = serde_json::json!({
+            "code": 0,
+            "result": {
+                "dash": null,
+                "durl": [
+                    { "order": 2, "length": 1000, "url": "https://example.com/2.flv" },
+                    { "order": 1, "length": 2000, "url": "https://example.com/1.flv" }
+                ],
+                "format": "flv",
+                "timelength": 3000
+            }
+        });
+        let dash = pgc_dash(&v, 1).unwrap();
+        let durl = dash.durl.expect("应退回 durl");
+        assert_eq!(durl.segments.len(), 2);
+        assert_eq!(durl.segments[0].order, 1);
+        assert_eq!(dash.duration, Some(3));
+    }
+}
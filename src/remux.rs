@@ -0,0 +1,616 @@
+//! 纯 Rust 的 DASH -> HLS 分片映射：B 站 DASH 轨均为带 `sidx` 索引的单文件 fMP4，
+//! 读取 init（ftyp+moov）与 sidx 后即可生成 `EXT-X-BYTERANGE` 形式的 VOD playlist，
+//! 分片请求按 Range 透传到上游，无需 FFmpeg，也无需等待转码。
+
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse, Responder, routes, web};
+use anyhow::{Result, anyhow};
+use once_cell::sync::Lazy;
+use reqwest::Client;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::hls::{self, AudioPref};
+use crate::playurl::{PlayAudio, PlayVideo, PlayurlDash, SegmentBase};
+
+/// 未给出 SegmentBase 时首次探测的字节数，通常足以覆盖 ftyp + moov + sidx
+const PROBE_BYTES: u64 = 64 * 1024;
+/// sidx 大小上限，超出时视为损坏，避免按虚假大小请求大量数据
+const MAX_SIDX_BYTES: u64 = 16 * 1024 * 1024;
+/// 上游地址未带 deadline 时的默认缓存时长
+const DEFAULT_TTL: Duration = Duration::from_secs(30 * 60);
+
+/// sidx 中的一个子分片（对应 HLS 的一个分片）
+#[derive(Debug, Clone)]
+pub struct SidxEntry {
+    pub offset: u64,
+    pub size: u64,
+    pub duration: f64,
+}
+
+/// 单条轨的索引：init 段长度（从 0 开始）与全部子分片
+#[derive(Debug, Clone)]
+pub struct TrackIndex {
+    pub init_len: u64,
+    pub segments: Vec<SidxEntry>,
+}
+
+#[derive(Debug, Clone)]
+struct ResolvedTrack {
+    url: String,
+    index: TrackIndex,
+    expires: Instant,
+}
+
+/// 已解析轨缓存：key = "{season_id}/{sort}/{track}"，在上游地址过期前复用
+static TRACKS: Lazy<Mutex<HashMap<String, ResolvedTrack>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[routes]
+#[get("/hls/{season_id}/{sort}/remux/{track}/index.m3u8")]
+#[get("/ugc/hls/{bvid}/{page}/remux/{track}/index.m3u8")]
+pub async fn remux_track_playlist(
+    path: web::Path<(String, String, String)>,
+    data: web::Data<crate::AppState>,
+    req: HttpRequest,
+) -> impl Responder {
+    let (season_id, sort, track) = path.into_inner();
+    let file = format!("remux/{}/index.m3u8", track);
+    if let Some(resp) = hls::redirect_to_ep(&data.client, &season_id, &sort, &file, &req).await {
+        return resp;
+    }
+    match resolve_track(&data.client, &season_id, &sort, &track).await {
+        Ok(t) => HttpResponse::Ok()
+            .insert_header(("Content-Type", "application/vnd.apple.mpegurl"))
+            .insert_header(("Cache-Control", "no-store"))
+            .body(render_media_playlist(&t.index)),
+        Err(e) => HttpResponse::InternalServerError()
+            .insert_header(("Content-Type", "text/plain; charset=utf-8"))
+            .body(format!("解析轨索引失败: {e}")),
+    }
+}
+
+/// 透传 Range 请求到上游 CDN
+#[routes]
+#[get("/hls/{season_id}/{sort}/remux/{track}/media.m4s")]
+#[get("/ugc/hls/{bvid}/{page}/remux/{track}/media.m4s")]
+pub async fn remux_media(
+    path: web::Path<(String, String, String)>,
+    req: HttpRequest,
+    data: web::Data<crate::AppState>,
+) -> impl Responder {
+    let (season_id, sort, track) = path.into_inner();
+    let file = format!("remux/{}/media.m4s", track);
+    if let Some(resp) = hls::redirect_to_ep(&data.client, &season_id, &sort, &file, &req).await {
+        return resp;
+    }
+    let resolved = match resolve_track(&data.client, &season_id, &sort, &track).await {
+        Ok(t) => t,
+        Err(e) => return HttpResponse::NotFound().body(format!("轨不存在: {e}")),
+    };
+    let mut upstream = data
+        .client
+        .get(&resolved.url)
+        .header("Referer", "https://www.bilibili.com")
+        .header("Accept-Encoding", "identity");
+    if let Some(range) = req
+        .headers()
+        .get(actix_web::http::header::RANGE)
+        .and_then(|v| v.to_str().ok())
+    {
+        upstream = upstream.header("Range", range);
+    }
+    let resp = match upstream.send().await {
+        Ok(r) => r,
+        Err(e) => return HttpResponse::BadGateway().body(format!("上游请求失败: {e}")),
+    };
+    if resp.status() == reqwest::StatusCode::FORBIDDEN {
+        // 上游地址失效，丢弃缓存，下次请求重新获取
+        forget_track(&season_id, &sort, &track);
+    }
+    let status = StatusCode::from_u16(resp.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+    let mut builder = HttpResponse::build(status);
+    builder
+        .insert_header(("Content-Type", "video/mp4"))
+        .insert_header(("Accept-Ranges", "bytes"))
+        .insert_header(("Cache-Control", "public, max-age=86400"));
+    for name in ["Content-Range", "Content-Length"] {
+        if let Some(v) = resp.headers().get(name).and_then(|v| v.to_str().ok()) {
+            builder.insert_header((name, v.to_string()));
+        }
+    }
+    let body = futures::stream::unfold(Some(resp), |state| async move {
+        let mut resp = state?;
+        match resp.chunk().await {
+            Ok(Some(bytes)) => Some((Ok(bytes), Some(resp))),
+            Ok(None) => None,
+            Err(e) => Some((Err(e), None)),
+        }
+    });
+    builder.streaming(body)
+}
+
+/// 轨标识：视频 `v{qn}-{codecid}`，音频 `a{id}`
+pub fn video_track_key(v: &PlayVideo) -> String {
+    format!("v{}-{}", v.id, v.codecid.unwrap_or(0))
+}
+
+pub fn audio_track_key(a: &PlayAudio) -> String {
+    format!("a{}", a.id)
+}
+
+/// 生成 remux 模式的 master playlist：`videos`（已按画质偏好筛选）作为变体，每种音轨类型（标准/杜比/无损）
+/// 取最高码率的一条列入 AUDIO 组，默认选中与 `audio` 同类型的音轨
+pub fn render_master_playlist(
+    dash: &PlayurlDash,
+    videos: &[&PlayVideo],
+    audio: &PlayAudio,
+) -> Result<String> {
+    if videos.is_empty() {
+        return Err(anyhow!("无视频轨"));
+    }
+    let renditions = hls::audio_renditions(dash);
+    let default_kind = AudioPref::of(audio);
+    let audio_codecs = audio.codecs.as_deref().unwrap_or("mp4a.40.2");
+    let mut out = String::from("#EXTM3U\n#EXT-X-VERSION:7\n#EXT-X-INDEPENDENT-SEGMENTS\n");
+    for a in &renditions {
+        let kind = AudioPref::of(a);
+        out.push_str(&format!(
+            "#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"{}\",DEFAULT={},AUTOSELECT=YES,CHANNELS=\"{}\",URI=\"remux/{}/index.m3u8\"\n",
+            kind.label(),
+            if kind == default_kind { "YES" } else { "NO" },
+            kind.channels(),
+            audio_track_key(a)
+        ));
+    }
+    let mut videos = videos.to_vec();
+    videos.sort_by_key(|v| std::cmp::Reverse(v.bandwidth.unwrap_or(0)));
+    for v in videos {
+        let bandwidth = v.bandwidth.unwrap_or(0) + audio.bandwidth.unwrap_or(0);
+        let mut attrs = vec![format!("BANDWIDTH={}", bandwidth)];
+        if let (Some(w), Some(h)) = (v.width, v.height) {
+            attrs.push(format!("RESOLUTION={}x{}", w, h));
+        }
+        if let Some(codecs) = &v.codecs {
+            attrs.push(format!("CODECS=\"{},{}\"", codecs, audio_codecs));
+        }
+        attrs.push("AUDIO=\"aud\"".to_string());
+        out.push_str(&format!(
+            "#EXT-X-STREAM-INF:{}\nremux/{}/index.m3u8\n",
+            attrs.join(","),
+            video_track_key(v)
+        ));
+    }
+    Ok(out)
+}
+
+/// 由轨索引生成完整的 VOD media playlist（所有分片都指向同一个代理资源的不同字节范围）
+pub fn render_media_playlist(index: &TrackIndex) -> String {
+    let target = index
+        .segments
+        .iter()
+        .map(|s| s.duration)
+        .fold(0.0_f64, f64::max)
+        .ceil()
+        .max(1.0) as u64;
+    let mut out = format!(
+        "#EXTM3U\n#EXT-X-VERSION:7\n#EXT-X-TARGETDURATION:{}\n#EXT-X-MEDIA-SEQUENCE:0\n#EXT-X-PLAYLIST-TYPE:VOD\n#EXT-X-INDEPENDENT-SEGMENTS\n",
+        target
+    );
+    out.push_str(&format!(
+        "#EXT-X-MAP:URI=\"media.m4s\",BYTERANGE=\"{}@0\"\n",
+        index.init_len
+    ));
+    for seg in &index.segments {
+        out.push_str(&format!(
+            "#EXTINF:{:.3},\n#EXT-X-BYTERANGE:{}@{}\nmedia.m4s\n",
+            seg.duration, seg.size, seg.offset
+        ));
+    }
+    out.push_str("#EXT-X-ENDLIST\n");
+    out
+}
+
+fn forget_track(season_id: &str, sort: &str, track: &str) {
+    if let Ok(mut map) = TRACKS.lock() {
+        map.remove(&format!("{}/{}/{}", season_id, sort, track));
+    }
+}
+
+async fn resolve_track(
+    client: &Client,
+    season_id: &str,
+    sort: &str,
+    track: &str,
+) -> Result<ResolvedTrack> {
+    let cache_key = format!("{}/{}/{}", season_id, sort, track);
+    if let Ok(map) = TRACKS.lock()
+        && let Some(t) = map.get(&cache_key)
+        && t.expires > Instant::now()
+    {
+        return Ok(t.clone());
+    }
+
+    let dash = hls::load_dash(client, season_id, sort).await?;
+    let (url, segment_base) =
+        if let Some(v) = dash.video.iter().find(|v| video_track_key(v) == track) {
+            (v.base_url.clone(), v.segment_base.clone())
+        } else if let Some(a) = dash.audio.iter().find(|a| audio_track_key(a) == track) {
+            (a.base_url.clone(), a.segment_base.clone())
+        } else {
+            return Err(anyhow!("未找到轨 {}", track));
+        };
+    let index = fetch_track_index(client, &url, segment_base.as_ref()).await?;
+    log::info!(
+        "remux 索引完成: {} segments={} init_len={}",
+        cache_key,
+        index.segments.len(),
+        index.init_len
+    );
+    let resolved = ResolvedTrack {
+        expires: Instant::now() + url_ttl(&url),
+        url,
+        index,
+    };
+    if let Ok(mut map) = TRACKS.lock() {
+        map.insert(cache_key, resolved.clone());
+    }
+    Ok(resolved)
+}
+
+/// 上游地址的 `deadline` 参数为过期时间戳，提前 5 分钟失效
+fn url_ttl(url: &str) -> Duration {
+    let deadline = reqwest::Url::parse(url).ok().and_then(|u| {
+        u.query_pairs()
+            .find(|(k, _)| k == "deadline")
+            .and_then(|(_, v)| v.parse::<u64>().ok())
+    });
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    match deadline {
+        Some(d) if d > now + 300 => Duration::from_secs(d - now - 300),
+        Some(_) => Duration::from_secs(0),
+        None => DEFAULT_TTL,
+    }
+}
+
+async fn fetch_range(
+    client: &Client,
+    url: &str,
+    start: u64,
+    end_inclusive: u64,
+) -> Result<Vec<u8>> {
+    if end_inclusive < start {
+        return Err(anyhow!("Range 非法 {}-{}", start, end_inclusive));
+    }
+    let mut resp = client
+        .get(url)
+        .header("Referer", "https://www.bilibili.com")
+        .header("Accept-Encoding", "identity")
+        .header("Range", format!("bytes={}-{}", start, end_inclusive))
+        .send()
+        .await?;
+    let status = resp.status();
+    if !status.is_success() {
+        return Err(anyhow!("Range 请求失败 status={}", status));
+    }
+    // 不支持 Range 的上游返回 200 与完整文件：读到所需位置即停止，再截取需要的部分
+    let skip = if status == reqwest::StatusCode::PARTIAL_CONTENT {
+        0
+    } else {
+        start as usize
+    };
+    let want = skip + (end_inclusive - start + 1) as usize;
+    let mut bytes = Vec::new();
+    while bytes.len() < want {
+        match resp.chunk().await? {
+            Some(chunk) => bytes.extend_from_slice(&chunk),
+            None => break,
+        }
+    }
+    bytes.truncate(want);
+    Ok(bytes.split_off(skip.min(bytes.len())))
+}
+
+/// 解析 "start-end" 形式的字节范围
+fn parse_range(s: &str) -> Option<(u64, u64)> {
+    let (a, b) = s.split_once('-')?;
+    Some((a.trim().parse().ok()?, b.trim().parse().ok()?))
+}
+
+pub async fn fetch_track_index(
+    client: &Client,
+    url: &str,
+    segment_base: Option<&SegmentBase>,
+) -> Result<TrackIndex> {
+    // 优先使用 DASH 给出的 SegmentBase，只需一次 Range 请求
+    if let Some(sb) = segment_base
+        && let (Some((_, init_end)), Some((idx_start, idx_end))) = (
+            parse_range(&sb.initialization),
+            parse_range(&sb.index_range),
+        )
+    {
+        if idx_end.saturating_sub(idx_start) >= MAX_SIDX_BYTES {
+            return Err(anyhow!("indexRange 非法: {}", sb.index_range));
+        }
+        let buf = fetch_range(client, url, idx_start, idx_end).await?;
+        let segments = parse_sidx(&buf, idx_start)?;
+        return Ok(TrackIndex {
+            init_len: init_end + 1,
+            segments,
+        });
+    }
+
+    // 否则读取文件头部，逐个 box 查找 moov 与 sidx
+    let mut buf = fetch_range(client, url, 0, PROBE_BYTES - 1).await?;
+    let mut init_len = None;
+    let mut pos: u64 = 0;
+    loop {
+        let Some((size, kind, header_len)) = read_box_header(&buf, pos as usize) else {
+            // box 头不完整，再多读一些
+            let more = fetch_range(
+                client,
+                url,
+                buf.len() as u64,
+                buf.len() as u64 + PROBE_BYTES - 1,
+            )
+            .await?;
+            if more.is_empty() {
+                return Err(anyhow!("未找到 sidx"));
+            }
+            buf.extend_from_slice(&more);
+            continue;
+        };
+        // size 为 0（延伸到文件末尾）或小于头部长度时无法继续定位后面的 box
+        if size < header_len as u64 {
+            return Err(anyhow!("box 大小非法"));
+        }
+        match &kind {
+            b"moov" => init_len = Some(pos + size),
+            b"sidx" => {
+                if size > MAX_SIDX_BYTES {
+                    return Err(anyhow!("sidx 大小非法: {}", size));
+                }
+                let end = pos + size;
+                if (buf.len() as u64) < end {
+                    let more = fetch_range(client, url, buf.len() as u64, end - 1).await?;
+                    buf.extend_from_slice(&more);
+                }
+                let segments = parse_sidx(box_slice(&buf, pos, size)?, pos)?;
+                let init_len = init_len.ok_or_else(|| anyhow!("sidx 之前未找到 moov"))?;
+                return Ok(TrackIndex { init_len, segments });
+            }
+            b"moof" | b"mdat" => return Err(anyhow!("文件不含 sidx 索引")),
+            _ => {}
+        }
+        pos = pos
+            .checked_add(size)
+            .ok_or_else(|| anyhow!("box 大小非法"))?;
+    }
+}
+
+/// 取出从 `pos` 开始、长 `size` 的 box；上游返回的数据不足时报错
+fn box_slice(buf: &[u8], pos: u64, size: u64) -> Result<&[u8]> {
+    let end = pos
+        .checked_add(size)
+        .ok_or_else(|| anyhow!("box 大小非法"))?;
+    buf.get(pos as usize..end as usize)
+        .ok_or_else(|| anyhow!("box 数据不完整: 需要 {} 字节，只有 {}", end, buf.len()))
+}
+
+/// 读取 box 头：返回 (box 总大小, 类型, 头部长度)。size32 为 1 时其后 8 字节为 largesize，头部长 16
+fn read_box_header(buf: &[u8], pos: usize) -> Option<(u64, [u8; 4], usize)> {
+    let head = buf.get(pos..pos + 8)?;
+    let size32 = u32::from_be_bytes(head[0..4].try_into().ok()?) as u64;
+    let kind: [u8; 4] = head[4..8].try_into().ok()?;
+    if size32 == 1 {
+        let size = u64::from_be_bytes(buf.get(pos + 8..pos + 16)?.try_into().ok()?);
+        Some((size, kind, 16))
+    } else {
+        Some((size32, kind, 8))
+    }
+}
+
+/// 解析 sidx box（ISO/IEC 14496-12 8.16.3）。`box_offset` 为该 box 在文件中的起始位置，
+/// 分片的文件偏移 = sidx 结束位置 + first_offset + 之前分片大小之和。
+pub fn parse_sidx(buf: &[u8], box_offset: u64) -> Result<Vec<SidxEntry>> {
+    let (size, kind, header_len) =
+        read_box_header(buf, 0).ok_or_else(|| anyhow!("sidx 数据不完整"))?;
+    if &kind != b"sidx" {
+        return Err(anyhow!("不是 sidx box"));
+    }
+    if size < header_len as u64 || (buf.len() as u64) < size {
+        return Err(anyhow!("sidx 大小非法: {}，数据 {} 字节", size, buf.len()));
+    }
+    let mut r = Reader {
+        buf,
+        pos: header_len,
+    };
+    let version = r.u8()?;
+    r.skip(3)?; // flags
+    let _reference_id = r.u32()?;
+    let timescale = r.u32()?;
+    if timescale == 0 {
+        return Err(anyhow!("sidx timescale 为 0"));
+    }
+    let first_offset = if version == 0 {
+        r.u32()?; // earliest_presentation_time
+        r.u32()? as u64
+    } else {
+        r.u64()?;
+        r.u64()?
+    };
+    r.skip(2)?; // reserved
+    let count = r.u16()?;
+    let mut offset = box_offset + size + first_offset;
+    let mut out = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let reference = r.u32()?;
+        let duration = r.u32()?;
+        r.u32()?; // SAP
+        if reference & 0x8000_0000 != 0 {
+            return Err(anyhow!("不支持层级 sidx"));
+        }
+        let ref_size = (reference & 0x7fff_ffff) as u64;
+        out.push(SidxEntry {
+            offset,
+            size: ref_size,
+            duration: duration as f64 / timescale as f64,
+        });
+        offset += ref_size;
+    }
+    Ok(out)
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8]> {
+        let s = self
+            .buf
+            .get(self.pos..self.pos + n)
+            .ok_or_else(|| anyhow!("sidx 数据截断"))?;
+        self.pos += n;
+        Ok(s)
+    }
+    fn skip(&mut self, n: usize) -> Result<()> {
+        self.take(n).map(|_| ())
+    }
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into()?))
+    }
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into()?))
+    }
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 拼出一个 sidx box：`largesize` 为真时使用 size32 = 1 + 64 位 largesize 的头部
+    fn sidx_box(version: u8, largesize: bool, first_offset: u64, refs: &[(u32, u32)]) -> Vec<u8> {
+        let mut body = vec![version, 0, 0, 0];
+        body.extend_from_slice(&1u32.to_be_bytes()); // reference_id
+        body.extend_from_slice(&1000u32.to_be_bytes()); // timescale
+        if version == 0 {
+            body.extend_from_slice(&0u32.to_be_bytes());
+            body.extend_from_slice(&(first_offset as u32).to_be_bytes());
+        } else {
+            body.extend_from_slice(&0u64.to_be_bytes());
+            body.extend_from_slice(&first_offset.to_be_bytes());
+        }
+        body.extend_from_slice(&[0, 0]);
+        body.extend_from_slice(&(refs.len() as u16).to_be_bytes());
+        for &(size, duration) in refs {
+            body.extend_from_slice(&size.to_be_bytes());
+            body.extend_from_slice(&duration.to_be_bytes());
+            body.extend_from_slice(&0x9000_0000u32.to_be_bytes());
+        }
+        let mut out = Vec::new();
+        if largesize {
+            out.extend_from_slice(&1u32.to_be_bytes());
+            out.extend_from_slice(b"sidx");
+            out.extend_from_slice(&(body.len() as u64 + 16).to_be_bytes());
+        } else {
+            out.extend_from_slice(&(body.len() as u32 + 8).to_be_bytes());
+            out.extend_from_slice(b"sidx");
+        }
+        out.extend_from_slice(&body);
+        out
+    }
+
+    #[test]
+    fn reads_compact_and_large_box_headers() {
+        let compact = sidx_box(0, false, 0, &[(100, 2000)]);
+        assert_eq!(
+            read_box_header(&compact, 0),
+            Some((compact.len() as u64, *b"sidx", 8))
+        );
+        // largesize 很小时也必须按 size32 == 1 识别，不能按数值大小猜测
+        let large = sidx_box(0, true, 0, &[(100, 2000)]);
+        assert_eq!(
+            read_box_header(&large, 0),
+            Some((large.len() as u64, *b"sidx", 16))
+        );
+        assert_eq!(read_box_header(&large[..12], 0), None);
+        assert_eq!(read_box_header(&compact, compact.len() - 4), None);
+    }
+
+    #[test]
+    fn parses_v0_sidx() {
+        let buf = sidx_box(0, false, 16, &[(1000, 2000), (1500, 2500)]);
+        let segs = parse_sidx(&buf, 800).unwrap();
+        let start = 800 + buf.len() as u64 + 16;
+        assert_eq!(segs.len(), 2);
+        assert_eq!((segs[0].offset, segs[0].size), (start, 1000));
+        assert_eq!((segs[1].offset, segs[1].size), (start + 1000, 1500));
+        assert_eq!(segs[0].duration, 2.0);
+        assert_eq!(segs[1].duration, 2.5);
+    }
+
+    #[test]
+    fn parses_v1_sidx() {
+        let buf = sidx_box(1, false, 0, &[(4000, 5000)]);
+        let segs = parse_sidx(&buf, 1200).unwrap();
+        assert_eq!(segs.len(), 1);
+        assert_eq!(segs[0].offset, 1200 + buf.len() as u64);
+        assert_eq!(segs[0].size, 4000);
+        assert_eq!(segs[0].duration, 5.0);
+    }
+
+    #[test]
+    fn parses_sidx_with_largesize_header() {
+        for version in [0, 1] {
+            let buf = sidx_box(version, true, 8, &[(700, 1000), (900, 1000)]);
+            let segs = parse_sidx(&buf, 0).unwrap();
+            assert_eq!(segs[0].offset, buf.len() as u64 + 8);
+            assert_eq!(segs[1].offset, buf.len() as u64 + 8 + 700);
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_or_foreign_box() {
+        let buf = sidx_box(0, false, 0, &[(1000, 2000)]);
+        assert!(parse_sidx(&buf[..buf.len() - 4], 0).is_err());
+        let mut moof = buf.clone();
+        moof[4..8].copy_from_slice(b"moof");
+        assert!(parse_sidx(&moof, 0).is_err());
+    }
+
+    #[test]
+    fn rejects_undersized_box() {
+        // size 小于头部长度时不能按头部之后的字节继续解析
+        for size in [0u32, 4, 7] {
+            let mut buf = sidx_box(0, false, 0, &[(1000, 2000)]);
+            buf[0..4].copy_from_slice(&size.to_be_bytes());
+            assert!(parse_sidx(&buf, 0).is_err(), "size {size}");
+        }
+        let mut large = sidx_box(0, true, 0, &[(1000, 2000)]);
+        large[8..16].copy_from_slice(&12u64.to_be_bytes());
+        assert!(parse_sidx(&large, 0).is_err());
+    }
+
+    #[test]
+    fn box_slice_checks_length() {
+        let buf = sidx_box(0, false, 0, &[(1000, 2000)]);
+        let len = buf.len() as u64;
+        assert_eq!(box_slice(&buf, 0, len).unwrap(), &buf[..]);
+        // 上游返回的字节少于 box 声明的大小
+        assert!(box_slice(&buf[..buf.len() - 1], 0, len).is_err());
+        assert!(box_slice(&buf, 4, len).is_err());
+        assert!(box_slice(&buf, u64::MAX, 2).is_err());
+    }
+}
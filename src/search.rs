@@ -1,13 +1,53 @@
 use anyhow::{Result, anyhow};
+use once_cell::sync::Lazy;
 use regex::Regex;
 use reqwest::Client;
 use std::collections::HashSet;
 
 use crate::wbi; // WBI 签名
 
+/// 搜索结果标题中高亮关键词的 <em class="keyword"> 标签
+static EM_TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r"</?em[^>]*>").unwrap());
+
+/// 可搜索的 PGC 分类：番剧（media_bangumi）与影视（media_ft，剧场版动画、电影、纪录片等）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MediaType {
+    Bangumi,
+    Ft,
+}
+
+impl MediaType {
+    pub const ALL: [MediaType; 2] = [MediaType::Bangumi, MediaType::Ft];
+
+    fn search_type(self) -> &'static str {
+        match self {
+            MediaType::Bangumi => "media_bangumi",
+            MediaType::Ft => "media_ft",
+        }
+    }
+
+    /// 搜索结果中的 category 字段
+    pub fn category(self) -> &'static str {
+        match self {
+            MediaType::Bangumi => "番剧",
+            MediaType::Ft => "影视",
+        }
+    }
+
+    /// `?category=bangumi|ft`
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "bangumi" | "media_bangumi" => Some(MediaType::Bangumi),
+            "ft" | "media_ft" => Some(MediaType::Ft),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug)]
 #[allow(dead_code)]
-pub struct MediaBangumiItem {
+pub struct MediaItem {
+    pub media_type: MediaType,
     pub title: String,
     pub media_id: i64,
     pub season_id: i64,
@@ -19,163 +59,223 @@ pub struct MediaBangumiItem {
     pub pub_time: Option<String>,
 }
 
-/// 自动翻页搜索番剧（media_bangumi），直到没有新结果或达最大页数
-/// 说明：B 站该接口没有返回总页数字段（或字段不稳定），这里通过“当前页无结果”作为停止条件，
-/// 另外加入 `MAX_PAGES` 保护，避免潜在无限循环。
-pub async fn search_media_bangumi(client: &Client, keyword: &str) -> Result<Vec<MediaBangumiItem>> {
-    const MAX_PAGES: u32 = 100; // 安全上限，可视需要调整 / 暴露为配置
-    let em_re = Regex::new(r"</?em[^>]*>").unwrap();
-    let mut page: u32 = 1;
-    let mut out: Vec<MediaBangumiItem> = Vec::new();
-    let mut seen: HashSet<i64> = HashSet::new(); // season_id 去重（或可换 media_id）
-
-    loop {
-        let params = vec![
-            ("keyword", keyword.to_string()),
-            ("search_type", "media_bangumi".to_string()),
-            ("page", page.to_string()),
-        ];
-        let query = wbi::sign_wbi(
-            client,
-            &params
-                .iter()
-                .map(|(k, v)| (*k, v.clone()))
-                .collect::<Vec<_>>(),
-        )
-        .await?;
-        let url = format!(
-            "https://api.bilibili.com/x/web-interface/wbi/search/type?{}",
-            query
-        );
-        eprintln!("[search] requesting page={} url={}", page, url);
-
-        let resp = client
-            .get(&url)
-            .header("Referer", "https://www.bilibili.com")
-            .header("Origin", "https://www.bilibili.com")
-            .send()
-            .await?;
-        let status = resp.status();
-        let bytes = resp.bytes().await?;
-        let resp_v: serde_json::Value = serde_json::from_slice(&bytes).map_err(|e| {
-            let text_preview = String::from_utf8_lossy(&bytes);
-            let text_preview = if text_preview.len() > 300 {
-                &text_preview[..300]
-            } else {
-                &text_preview
-            };
-            anyhow!(
-                "解析JSON失败 status={} err={} body_preview=<<<{}>>>",
-                status,
-                e,
-                text_preview
-            )
-        })?;
-
-        let code = resp_v.get("code").and_then(|c| c.as_i64()).unwrap_or(-1);
-        if code != 0 {
-            return Err(anyhow!(
-                "搜索失败 page={} code={}: {:?}",
-                page,
-                code,
-                resp_v
-            ));
-        }
+#[derive(Debug)]
+pub struct VideoItem {
+    pub bvid: String,
+    pub title: String,
+    pub pic: String,
+    pub description: String,
+    pub pubdate: i64,
+}
 
-        let results = resp_v.get("data").and_then(|d| d.get("result"));
-        // 空或缺失 => 终止
-        if results.is_none() {
-            break;
-        }
+/// 某一分类搜索的一页结果
+#[derive(Debug)]
+pub struct MediaPage {
+    pub items: Vec<MediaItem>,
+    /// 上游给出的总页数与总结果数；缺失时未知
+    pub num_pages: Option<u32>,
+    pub num_results: Option<usize>,
+}
 
-        let mut page_new_items = 0usize;
-
-        let push_item = |item: &serde_json::Value,
-                         em_re: &Regex,
-                         seen: &mut HashSet<i64>,
-                         out: &mut Vec<MediaBangumiItem>,
-                         page_new_items: &mut usize| {
-            let title_raw = item.get("title").and_then(|v| v.as_str()).unwrap_or("");
-            let title = html_unescape(&em_re.replace_all(title_raw, ""));
-            let media_id = item.get("media_id").and_then(|v| v.as_i64()).unwrap_or(0);
-            let season_id = item.get("season_id").and_then(|v| v.as_i64()).unwrap_or(0);
-            let eps = item.get("eps").and_then(|v| v.as_i64()).unwrap_or(0);
-            if season_id != 0 && !seen.insert(season_id) {
-                return;
-            } // 已存在跳过
-            let cover = item
-                .get("cover")
-                .or_else(|| item.get("media_cover"))
-                .or_else(|| item.get("season_cover"))
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string());
-            let desc = item
-                .get("desc")
-                .or_else(|| item.get("media_desc"))
-                .or_else(|| item.get("evaluate"))
-                .and_then(|v| v.as_str())
-                .map(|s| html_unescape(s));
-            let is_finish = item
-                .get("is_finish")
-                .or_else(|| item.get("finish"))
-                .and_then(|v| match v {
-                    serde_json::Value::Bool(b) => Some(*b),
-                    serde_json::Value::Number(n) => n.as_i64().map(|i| i == 1),
-                    _ => None,
-                });
-            let season_type_name = item
-                .get("season_type_name")
-                .or_else(|| item.get("media_type_name"))
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string());
-            let pub_time = item
-                .get("pub_time")
-                .or_else(|| item.get("pubtime"))
-                .or_else(|| item.get("publish_time"))
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string());
-            out.push(MediaBangumiItem {
-                title,
-                media_id,
-                season_id,
-                eps,
-                cover,
-                desc,
-                is_finish,
-                season_type_name,
-                pub_time,
-            });
-            *page_new_items += 1;
+/// 请求分类搜索接口的某一页，返回完整 JSON（code 非 0 时报错）；`order` 原样传给上游
+async fn search_type_page(
+    client: &Client,
+    keyword: &str,
+    search_type: &str,
+    page: u32,
+    order: Option<&str>,
+) -> Result<serde_json::Value> {
+    let mut params = vec![
+        ("keyword", keyword.to_string()),
+        ("search_type", search_type.to_string()),
+        ("page", page.to_string()),
+    ];
+    if let Some(order) = order {
+        params.push(("order", order.to_string()));
+    }
+    let query = wbi::sign_wbi(
+        client,
+        &params
+            .iter()
+            .map(|(k, v)| (*k, v.clone()))
+            .collect::<Vec<_>>(),
+    )
+    .await?;
+    let url = format!(
+        "https://api.bilibili.com/x/web-interface/wbi/search/type?{}",
+        query
+    );
+    log::debug!(
+        "[search] requesting type={} page={} url={}",
+        search_type,
+        page,
+        url
+    );
+
+    let resp = client
+        .get(&url)
+        .header("Referer", "https://www.bilibili.com")
+        .header("Origin", "https://www.bilibili.com")
+        .send()
+        .await?;
+    let status = resp.status();
+    let bytes = resp.bytes().await?;
+    let resp_v: serde_json::Value = serde_json::from_slice(&bytes).map_err(|e| {
+        let text_preview = String::from_utf8_lossy(&bytes);
+        let text_preview = if text_preview.len() > 300 {
+            &text_preview[..300]
+        } else {
+            &text_preview
         };
+        anyhow!(
+            "解析JSON失败 status={} err={} body_preview=<<<{}>>>",
+            status,
+            e,
+            text_preview
+        )
+    })?;
 
-        match results.unwrap() {
-            serde_json::Value::Array(arr) => {
-                for item in arr.iter() {
-                    push_item(item, &em_re, &mut seen, &mut out, &mut page_new_items);
-                }
+    let code = resp_v.get("code").and_then(|c| c.as_i64()).unwrap_or(-1);
+    if code != 0 {
+        return Err(anyhow!(
+            "搜索失败 page={} code={}: {:?}",
+            page,
+            code,
+            resp_v
+        ));
+    }
+    Ok(resp_v)
+}
+
+/// 搜索普通视频（search_type=video），只取第一页，避免番剧结果被大量视频淹没
+pub async fn search_video(client: &Client, keyword: &str) -> Result<Vec<VideoItem>> {
+    let resp_v = search_type_page(client, keyword, "video", 1, None).await?;
+    let results = resp_v
+        .get("data")
+        .and_then(|d| d.get("result"))
+        .and_then(|r| r.as_array())
+        .cloned()
+        .unwrap_or_default();
+    let str_of = |item: &serde_json::Value, key: &str| {
+        item.get(key)
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string()
+    };
+    Ok(results
+        .iter()
+        .filter(|item| item.get("type").and_then(|t| t.as_str()) == Some("video"))
+        .filter_map(|item| {
+            let bvid = str_of(item, "bvid");
+            if bvid.is_empty() {
+                return None;
             }
-            serde_json::Value::Object(map) => {
-                for (_k, v) in map.iter() {
-                    if let serde_json::Value::Array(arr) = v {
-                        for item in arr {
-                            push_item(item, &em_re, &mut seen, &mut out, &mut page_new_items);
-                        }
-                    }
+            let pic = str_of(item, "pic");
+            Some(VideoItem {
+                bvid,
+                title: html_unescape(&EM_TAG.replace_all(&str_of(item, "title"), "")),
+                // 搜索结果的封面通常省略协议
+                pic: if pic.starts_with("//") {
+                    format!("https:{}", pic)
+                } else {
+                    pic
+                },
+                description: html_unescape(&str_of(item, "description")),
+                pubdate: item.get("pubdate").and_then(|v| v.as_i64()).unwrap_or(0),
+            })
+        })
+        .collect())
+}
+
+/// 搜索某一分类的第 `page` 页（从 1 开始），由调用方按需翻页
+pub async fn search_media(
+    client: &Client,
+    media_type: MediaType,
+    keyword: &str,
+    page: u32,
+    order: Option<&str>,
+) -> Result<MediaPage> {
+    let resp_v = search_type_page(client, keyword, media_type.search_type(), page, order).await?;
+    let data = resp_v.get("data");
+    let count_of = |key: &str| data.and_then(|d| d.get(key)).and_then(|v| v.as_u64());
+    let mut out: Vec<MediaItem> = Vec::new();
+    let mut seen: HashSet<i64> = HashSet::new(); // season_id 去重
+
+    let mut push_item = |item: &serde_json::Value| {
+        let title_raw = item.get("title").and_then(|v| v.as_str()).unwrap_or("");
+        let title = html_unescape(&EM_TAG.replace_all(title_raw, ""));
+        let media_id = item.get("media_id").and_then(|v| v.as_i64()).unwrap_or(0);
+        let season_id = item.get("season_id").and_then(|v| v.as_i64()).unwrap_or(0);
+        let eps = item.get("eps").and_then(|v| v.as_i64()).unwrap_or(0);
+        if season_id != 0 && !seen.insert(season_id) {
+            return;
+        } // 已存在跳过
+        let cover = item
+            .get("cover")
+            .or_else(|| item.get("media_cover"))
+            .or_else(|| item.get("season_cover"))
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
+        let desc = item
+            .get("desc")
+            .or_else(|| item.get("media_desc"))
+            .or_else(|| item.get("evaluate"))
+            .and_then(|v| v.as_str())
+            .map(html_unescape);
+        let is_finish = item
+            .get("is_finish")
+            .or_else(|| item.get("finish"))
+            .and_then(|v| match v {
+                serde_json::Value::Bool(b) => Some(*b),
+                serde_json::Value::Number(n) => n.as_i64().map(|i| i == 1),
+                _ => None,
+            });
+        let season_type_name = item
+            .get("season_type_name")
+            .or_else(|| item.get("media_type_name"))
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
+        let pub_time = item
+            .get("pub_time")
+            .or_else(|| item.get("pubtime"))
+            .or_else(|| item.get("publish_time"))
+            .and_then(|v| match v {
+                serde_json::Value::String(s) => Some(s.clone()),
+                // pubtime 为秒级时间戳，此时直接换算为年份
+                serde_json::Value::Number(n) => n.as_i64().map(crate::ugc::year_of),
+                _ => None,
+            })
+            .filter(|s| !s.is_empty());
+        out.push(MediaItem {
+            media_type,
+            title,
+            media_id,
+            season_id,
+            eps,
+            cover,
+            desc,
+            is_finish,
+            season_type_name,
+            pub_time,
+        });
+    };
+
+    match data.and_then(|d| d.get("result")) {
+        Some(serde_json::Value::Array(arr)) => arr.iter().for_each(&mut push_item),
+        Some(serde_json::Value::Object(map)) => {
+            for v in map.values() {
+                if let serde_json::Value::Array(arr) = v {
+                    arr.iter().for_each(&mut push_item);
                 }
             }
-            _ => {}
-        }
-
-        // 当前页没有新增 => 结束
-        if page_new_items == 0 {
-            break;
-        }
-        page += 1;
-        if page > MAX_PAGES {
-            break;
         }
+        _ => {}
     }
-    Ok(out)
+    Ok(MediaPage {
+        items: out,
+        num_pages: count_of("numPages").map(|n| n as u32),
+        num_results: count_of("numResults").map(|n| n as usize),
+    })
 }
 
 fn html_unescape(s: &str) -> String {
@@ -0,0 +1,214 @@
+//! 番剧元数据服务：所有对 `pgc/view/web/season` 的请求都经过这里。
+//! 响应按 season_id 在内存中缓存 `[season] ttl_secs` 秒，同一 season（或 ep_id）的并发请求
+//! 合并为一次上游调用；开启 `persist` 后同时写入 `cache_dir/season`，重启后从磁盘恢复。
+
+use anyhow::{Context, Result, anyhow};
+use futures::future::{BoxFuture, FutureExt, Shared};
+use once_cell::sync::Lazy;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::bili::season::{self as model, Season};
+use crate::cache::now_secs;
+use crate::config;
+
+/// 缓存中的一条番剧信息，落盘时即为文件内容
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    /// 获取时间（Unix 秒），用于判断缓存是否过期
+    fetched_at: u64,
+    season: Arc<Season>,
+}
+
+/// 合并请求的键：按 season_id 或按 ep_id 请求
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Key {
+    Season(i64),
+    Ep(u64),
+}
+
+type Pending = Shared<BoxFuture<'static, Result<Arc<Season>, Arc<anyhow::Error>>>>;
+
+static SEASONS: Lazy<Mutex<HashMap<i64, Entry>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+/// ep_id → season_id，正片与 section 中的剧集都会登记
+static EP_INDEX: Lazy<Mutex<HashMap<u64, i64>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+/// 正在进行的上游请求，后到的调用方等待同一个结果
+static INFLIGHT: Lazy<Mutex<HashMap<Key, Pending>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+impl Entry {
+    fn is_fresh(&self) -> bool {
+        now_secs().saturating_sub(self.fetched_at) < config::get().season.ttl_secs
+    }
+}
+
+/// 按 season_id 获取番剧信息
+pub async fn get(client: &Client, season_id: i64) -> Result<Arc<Season>> {
+    if let Some(s) = cached(season_id) {
+        return Ok(s);
+    }
+    load(client, Key::Season(season_id)).await
+}
+
+/// 按 ep_id 获取其所属番剧的信息
+pub async fn get_by_ep(client: &Client, ep_id: u64) -> Result<Arc<Season>> {
+    let season_id = EP_INDEX.lock().ok().and_then(|m| m.get(&ep_id).copied());
+    if let Some(s) = season_id.and_then(cached) {
+        return Ok(s);
+    }
+    load(client, Key::Ep(ep_id)).await
+}
+
+fn cached(season_id: i64) -> Option<Arc<Season>> {
+    let map = SEASONS.lock().ok()?;
+    map.get(&season_id)
+        .filter(|e| e.is_fresh())
+        .map(|e| e.season.clone())
+}
+
+async fn load(client: &Client, key: Key) -> Result<Arc<Season>> {
+    let pending = {
+        let mut inflight = INFLIGHT
+            .lock()
+            .map_err(|_| anyhow!("season 请求表锁已损坏"))?;
+        inflight
+            .entry(key)
+            .or_insert_with(|| {
+                let client = client.clone();
+                async move {
+                    let result = fetch(&client, key).await.map(store).map_err(Arc::new);
+                    if let Ok(mut inflight) = INFLIGHT.lock() {
+                        inflight.remove(&key);
+                    }
+                    result
+                }
+                .boxed()
+                .shared()
+            })
+            .clone()
+    };
+    // anyhow::Error 不能 Clone，共享的错误按原文重新包装（保留 code=-412 等供 map_error_code 识别）
+    pending.await.map_err(|e| anyhow!("{e:#}"))
+}
+
+async fn fetch(client: &Client, key: Key) -> Result<Entry> {
+    let (name, value) = match key {
+        Key::Season(id) => ("season_id", id.to_string()),
+        Key::Ep(id) => ("ep_id", id.to_string()),
+    };
+    let mut url = reqwest::Url::parse("https://api.bilibili.com/pgc/view/web/season")?;
+    url.query_pairs_mut().append_pair(name, &value);
+    let resp = client
+        .get(url)
+        .header("Referer", "https://www.bilibili.com")
+        .send()
+        .await?;
+    let status = resp.status();
+    let text = resp.text().await?;
+    let v: Value = serde_json::from_str(&text).map_err(|e| {
+        anyhow!(
+            "season detail parse fail {}={} status={} err={} body_snip={}",
+            name,
+            value,
+            status,
+            e,
+            &text.chars().take(160).collect::<String>()
+        )
+    })?;
+    // 有的返回 result，有的返回 data
+    let root = v
+        .get("result")
+        .or_else(|| v.get("data"))
+        .filter(|r| !r.is_null())
+        .cloned()
+        .ok_or_else(|| {
+            let code = v.get("code").and_then(|c| c.as_i64()).unwrap_or(0);
+            anyhow!("season not found {}={} code={}", name, value, code)
+        })?;
+    let season = model::parse(root).with_context(|| format!("{}={}", name, value))?;
+    Ok(Entry {
+        fetched_at: now_secs(),
+        season: Arc::new(season),
+    })
+}
+
+/// 写入内存缓存与 ep 索引，开启持久化时同时落盘
+fn store(entry: Entry) -> Arc<Season> {
+    if config::get().season.persist {
+        persist(&entry);
+    }
+    let season = entry.season.clone();
+    remember(entry);
+    season
+}
+
+fn remember(entry: Entry) {
+    if config::get().season.ttl_secs == 0 {
+        return;
+    }
+    let season_id = entry.season.season_id;
+    if let Ok(mut index) = EP_INDEX.lock() {
+        for ep_id in entry.season.all_episodes().filter_map(|ep| ep.ep_id()) {
+            index.insert(ep_id, season_id);
+        }
+    }
+    if let Ok(mut map) = SEASONS.lock() {
+        map.retain(|_, e| e.is_fresh());
+        map.insert(season_id, entry);
+        if let Ok(mut index) = EP_INDEX.lock() {
+            index.retain(|_, id| map.contains_key(id));
+        }
+    }
+}
+
+fn persist_dir() -> PathBuf {
+    PathBuf::from(&config::get().api.cache_dir).join("season")
+}
+
+/// 先写临时文件再改名，与任务清单的写法一致
+fn persist(entry: &Entry) {
+    let dir = persist_dir();
+    let season_id = entry.season.season_id;
+    let path = dir.join(format!("{}.json", season_id));
+    let tmp = dir.join(format!("{}.json.tmp", season_id));
+    let result = fs::create_dir_all(&dir)
+        .and_then(|_| serde_json::to_vec(entry).map_err(std::io::Error::other))
+        .and_then(|bytes| fs::write(&tmp, bytes))
+        .and_then(|_| fs::rename(&tmp, &path));
+    if let Err(e) = result {
+        log::warn!("写入番剧缓存失败 {}: {e}", path.display());
+    }
+}
+
+/// 启动时从磁盘恢复未过期的番剧缓存，并删除已过期的文件
+pub fn restore_on_startup() {
+    if !config::get().season.persist {
+        return;
+    }
+    let Ok(entries) = fs::read_dir(persist_dir()) else {
+        return;
+    };
+    let mut restored = 0usize;
+    for path in entries.flatten().map(|e| e.path()) {
+        // 结构已变化的旧文件解析失败，与过期文件一样删除
+        let entry = fs::read_to_string(&path)
+            .ok()
+            .and_then(|text| serde_json::from_str::<Entry>(&text).ok());
+        match entry {
+            Some(e) if e.is_fresh() => {
+                remember(e);
+                restored += 1;
+            }
+            _ => {
+                let _ = fs::remove_file(&path);
+            }
+        }
+    }
+    if restored > 0 {
+        log::info!("从磁盘恢复了 {} 条番剧缓存", restored);
+    }
+}
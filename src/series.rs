@@ -0,0 +1,213 @@
+//! 系列视图：番剧接口的 `seasons[]` 列出同一系列的各季、剧场版与 OVA，
+//! `/series/{season_id}` 从任意一季出发返回整个系列。
+
+use actix_web::{HttpRequest, HttpResponse, Responder, get, web};
+use anyhow::Result;
+use futures::stream::{self, StreamExt};
+use reqwest::Client;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::bili::season::Season;
+use crate::{
+    ApiResult, AppState, dub, error_status, map_error_code, render_channels_html, season,
+    season_detail_of,
+};
+
+/// 获取各季番剧信息的并发数
+const CONCURRENCY: usize = 5;
+
+#[derive(Serialize)]
+struct SeriesSeason {
+    season_id: i64,
+    /// seasons[] 中的名称，如 第二季、剧场版
+    title: String,
+    cover: String,
+    year: String,
+    /// 正片集数
+    eps: usize,
+    status: String,
+    #[serde(rename = "type")]
+    type_field: String,
+    /// 是否为请求的那一季
+    current: bool,
+    url: String,
+}
+
+#[derive(Serialize)]
+struct SeriesData {
+    id: String,
+    title: String,
+    seasons: Vec<SeriesSeason>,
+}
+
+/// seasons[] 中的一季及其番剧信息（获取失败时为 None）
+struct SeasonEntry {
+    season_id: i64,
+    name: String,
+    cover: String,
+    season: Option<Arc<Season>>,
+}
+
+/// 系列标题与其中的每一季；配音版本已在对应原版的详情中作为线路列出，这里不再重复
+async fn fetch_series(client: &Client, season_id: i64) -> Result<(String, Vec<SeasonEntry>)> {
+    let current = season::get(client, season_id).await?;
+    let series_title = current
+        .series
+        .as_ref()
+        .map(|s| s.series_title.as_str())
+        .filter(|t| !t.is_empty())
+        .unwrap_or_else(|| current.display_title())
+        .to_string();
+    // 当前季本身是配音版时保留它，去掉它的原版与其它配音版
+    let mut dubs: HashSet<i64> = dub::dub_editions(&current.seasons);
+    dubs.extend(dub::siblings(&current).into_iter().map(|sib| sib.season_id));
+    dubs.remove(&season_id);
+    // (season_id, 名称, 封面)；没有 seasons[] 时系列只有当前这一季
+    let mut entries: Vec<(i64, String, String)> = current
+        .seasons
+        .iter()
+        .filter(|s| !dubs.contains(&s.season_id))
+        .map(|s| {
+            let name = if s.season_title.is_empty() {
+                &s.title
+            } else {
+                &s.season_title
+            };
+            (s.season_id, name.clone(), s.cover.clone())
+        })
+        .collect();
+    if !entries.iter().any(|(id, _, _)| *id == season_id) {
+        entries.insert(
+            0,
+            (
+                season_id,
+                current.display_title().to_string(),
+                current.cover.clone(),
+            ),
+        );
+    }
+    let current = &current;
+    let seasons = stream::iter(entries)
+        .map(|(id, name, cover)| async move {
+            let season = if id == season_id {
+                Some(current.clone())
+            } else {
+                season::get(client, id)
+                    .await
+                    .inspect_err(|e| log::warn!("获取系列中的季失败 season_id={} err={e:#}", id))
+                    .ok()
+            };
+            SeasonEntry {
+                season_id: id,
+                name,
+                cover,
+                season,
+            }
+        })
+        .buffered(CONCURRENCY)
+        .collect()
+        .await;
+    Ok((series_title, seasons))
+}
+
+/// `/series/{season_id}`：系列中的所有季；`?f=html` 时返回每季一个线路的条目页，供 Animeko 使用
+#[get("/series/{season_id}")]
+pub async fn series_endpoint(
+    path: web::Path<(String,)>,
+    q: web::Query<HashMap<String, String>>,
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> impl Responder {
+    let html_mode = q
+        .get("f")
+        .map(|v| v.eq_ignore_ascii_case("html"))
+        .unwrap_or(false);
+    let Ok(season_id) = path.into_inner().0.parse::<i64>() else {
+        return HttpResponse::BadRequest().json(ApiResult {
+            code: 400,
+            success: false,
+            message: "id 参数应为数字".into(),
+            data: serde_json::json!({}),
+        });
+    };
+    let (title, seasons) = match fetch_series(&data.client, season_id).await {
+        Ok(r) => r,
+        Err(e) => {
+            log::error!("series error id={} err={e:#}", season_id);
+            let (code, msg) = map_error_code(&e);
+            return HttpResponse::build(error_status(code)).json(ApiResult {
+                code,
+                success: false,
+                message: msg,
+                data: serde_json::json!({}),
+            });
+        }
+    };
+
+    if html_mode {
+        let base = crate::request_base(&data.public_base, &req);
+        // 只有条目页需要各季的剧集列表
+        let details: Vec<_> = seasons
+            .iter()
+            .filter_map(|s| {
+                let detail = season_detail_of(s.season.as_deref()?, &data.public_base)
+                    .inspect_err(|e| {
+                        log::warn!("解析系列中的季失败 season_id={} err={e:#}", s.season_id)
+                    })
+                    .ok()?;
+                Some((s.name.as_str(), detail))
+            })
+            .collect();
+        let channels = details
+            .iter()
+            .filter(|(_, d)| !d.sources.is_empty())
+            .map(|(name, d)| (*name, d.sources.as_slice()));
+        return HttpResponse::Ok()
+            .insert_header(("Content-Type", "text/html; charset=utf-8"))
+            .body(render_channels_html(&title, channels, &base));
+    }
+
+    let base = data.public_base.trim_end_matches('/');
+    let seasons = seasons
+        .into_iter()
+        .map(|entry| {
+            let id = entry.season_id;
+            let mut season = SeriesSeason {
+                season_id: id,
+                title: entry.name,
+                cover: entry.cover,
+                year: String::new(),
+                eps: 0,
+                status: String::new(),
+                type_field: String::new(),
+                current: id == season_id,
+                url: format!("{}/detail/{}", base, id),
+            };
+            if let Some(s) = entry.season {
+                if season.title.is_empty() {
+                    season.title = s.display_title().to_string();
+                }
+                if season.cover.is_empty() {
+                    season.cover = s.cover.clone();
+                }
+                season.year = s.year();
+                season.eps = s.episodes.len();
+                season.status = s.status().to_string();
+                season.type_field = s.type_name().to_string();
+            }
+            season
+        })
+        .collect();
+    HttpResponse::Ok().json(ApiResult {
+        code: 0,
+        success: true,
+        message: String::new(),
+        data: SeriesData {
+            id: season_id.to_string(),
+            title,
+            seasons,
+        },
+    })
+}
@@ -0,0 +1,329 @@
+//! CC 字幕：通过 player/wbi/v2 获取剧集的字幕列表，将 B 站 JSON 字幕转换为 WebVTT，
+//! 并以 `EXT-X-MEDIA TYPE=SUBTITLES` 挂到 master playlist 上。
+
+use actix_web::{HttpRequest, HttpResponse, Responder, routes, web};
+use anyhow::{Result, anyhow};
+use reqwest::Client;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::hls;
+
+/// master playlist 中的字幕组
+const GROUP_ID: &str = "subs";
+/// FFmpeg 输出 MPEG-TS 时默认的起始 PTS（1.4s × 90kHz），TS 模式的 WebVTT 需以此对齐
+const MPEGTS_START: u64 = 126000;
+
+/// 一条字幕轨
+#[derive(Debug, Clone)]
+pub struct SubtitleTrack {
+    /// 语言代码，如 zh-Hans、en、ai-zh
+    pub lan: String,
+    /// 显示名，如 中文（简体）
+    pub lan_doc: String,
+    pub url: String,
+}
+
+/// 剧集没有所请求语言的字幕，对应 404
+#[derive(Debug)]
+struct TrackNotFound(String);
+
+impl fmt::Display for TrackNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "字幕不存在: {}", self.0)
+    }
+}
+
+impl std::error::Error for TrackNotFound {}
+
+#[derive(Debug, Deserialize)]
+struct PlayerResp {
+    code: i32,
+    #[serde(default)]
+    message: String,
+    data: Option<PlayerData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PlayerData {
+    subtitle: Option<PlayerSubtitle>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PlayerSubtitle {
+    #[serde(default)]
+    subtitles: Vec<SubtitleItem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubtitleItem {
+    lan: String,
+    #[serde(default)]
+    lan_doc: String,
+    #[serde(default)]
+    subtitle_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubtitleBody {
+    #[serde(default)]
+    body: Vec<Cue>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Cue {
+    from: f64,
+    to: f64,
+    content: String,
+}
+
+/// 获取某一 P 的字幕列表
+pub async fn fetch_subtitle_list(
+    client: &Client,
+    aid: u64,
+    cid: u64,
+) -> Result<Vec<SubtitleTrack>> {
+    let params = [("aid", aid.to_string()), ("cid", cid.to_string())];
+    let wbi_params: Vec<(&str, String)> = params.iter().map(|(k, v)| (*k, v.clone())).collect();
+    let query = crate::wbi::sign_wbi(client, &wbi_params).await?;
+    let url = format!("https://api.bilibili.com/x/player/wbi/v2?{}", query);
+    let resp: PlayerResp = client
+        .get(&url)
+        .header("Referer", "https://www.bilibili.com")
+        .send()
+        .await?
+        .json()
+        .await?;
+    if resp.code != 0 {
+        return Err(anyhow!(
+            "获取字幕列表失败 code={} {}",
+            resp.code,
+            resp.message
+        ));
+    }
+    Ok(resp
+        .data
+        .and_then(|d| d.subtitle)
+        .map(|s| s.subtitles)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|s| !s.subtitle_url.is_empty())
+        .map(|s| SubtitleTrack {
+            lan_doc: if s.lan_doc.is_empty() {
+                s.lan.clone()
+            } else {
+                s.lan_doc
+            },
+            lan: s.lan,
+            // 接口返回的地址通常省略协议
+            url: if s.subtitle_url.starts_with("//") {
+                format!("https:{}", s.subtitle_url)
+            } else {
+                s.subtitle_url
+            },
+        })
+        .collect())
+}
+
+/// 获取剧集（season_id + sort 或 ep + ep_id）或普通视频（bvid + page）的字幕列表
+pub async fn episode_subtitles(
+    client: &Client,
+    season_id: &str,
+    sort: &str,
+) -> Result<Vec<SubtitleTrack>> {
+    let info = hls::resolve_episode(client, season_id, sort).await?;
+    fetch_subtitle_list(client, info.aid, info.cid).await
+}
+
+async fn fetch_cues(client: &Client, track: &SubtitleTrack) -> Result<Vec<Cue>> {
+    let body: SubtitleBody = client
+        .get(&track.url)
+        .header("Referer", "https://www.bilibili.com")
+        .send()
+        .await?
+        .json()
+        .await?;
+    Ok(body.body)
+}
+
+fn vtt_timestamp(secs: f64) -> String {
+    let ms = (secs.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        ms % 1000
+    )
+}
+
+/// JSON 字幕转 WebVTT；`mpegts` 为 true 时写入 X-TIMESTAMP-MAP 与 TS 分片的时间戳对齐
+fn render_vtt(cues: &[Cue], mpegts: bool) -> String {
+    let mut out = String::from("WEBVTT\n");
+    if mpegts {
+        out.push_str(&format!(
+            "X-TIMESTAMP-MAP=MPEGTS:{},LOCAL:00:00:00.000\n",
+            MPEGTS_START
+        ));
+    }
+    for (i, cue) in cues.iter().enumerate() {
+        // 空行会提前结束 cue，需要去掉；& < > 在 cue 文本中有特殊含义，需转义
+        let text = cue
+            .content
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(escape_cue_text)
+            .collect::<Vec<_>>()
+            .join("\n");
+        out.push_str(&format!(
+            "\n{}\n{} --> {}\n{}\n",
+            i + 1,
+            vtt_timestamp(cue.from),
+            vtt_timestamp(cue.to),
+            text
+        ));
+    }
+    out
+}
+
+fn escape_cue_text(line: &str) -> String {
+    line.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// 字幕的 media playlist：整条字幕作为单个分片
+fn render_playlist(cues: &[Cue], lan: &str, format: &str) -> String {
+    let duration = cues.iter().map(|c| c.to).fold(0.0_f64, f64::max).ceil() as u64;
+    format!(
+        "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:{}\n#EXT-X-MEDIA-SEQUENCE:0\n#EXT-X-PLAYLIST-TYPE:VOD\n#EXTINF:{}.000,\n{}.vtt?format={}\n#EXT-X-ENDLIST\n",
+        duration.max(1),
+        duration.max(1),
+        lan,
+        format
+    )
+}
+
+/// 在 master playlist 中加入字幕组，并为每个变体声明 SUBTITLES。
+/// 字幕地址相对于 `/hls/{season_id}/{sort}/master.m3u8`，普通视频相应落在 `/ugc/subtitle/` 下
+pub fn attach_to_master(
+    master: &str,
+    tracks: &[SubtitleTrack],
+    season_id: &str,
+    sort: &str,
+    format: &str,
+) -> String {
+    if tracks.is_empty() {
+        return master.to_string();
+    }
+    let media: String = tracks
+        .iter()
+        .map(|t| {
+            // AI 字幕的语言代码形如 ai-zh
+            let language = t.lan.strip_prefix("ai-").unwrap_or(&t.lan);
+            format!(
+                "#EXT-X-MEDIA:TYPE=SUBTITLES,GROUP-ID=\"{}\",NAME=\"{}\",LANGUAGE=\"{}\",DEFAULT=NO,AUTOSELECT=YES,URI=\"../../../subtitle/{}/{}/{}.m3u8?format={}\"\n",
+                GROUP_ID, t.lan_doc, language, season_id, sort, t.lan, format
+            )
+        })
+        .collect();
+    let mut out = String::new();
+    let mut inserted = false;
+    for line in master.lines() {
+        if let Some(attrs) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+            if !inserted {
+                out.push_str(&media);
+                inserted = true;
+            }
+            out.push_str(&format!(
+                "#EXT-X-STREAM-INF:{},SUBTITLES=\"{}\"\n",
+                attrs, GROUP_ID
+            ));
+        } else {
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+async fn find_track(
+    client: &Client,
+    season_id: &str,
+    sort: &str,
+    lang: &str,
+) -> Result<SubtitleTrack> {
+    episode_subtitles(client, season_id, sort)
+        .await?
+        .into_iter()
+        .find(|t| t.lan == lang)
+        .ok_or_else(|| TrackNotFound(lang.to_string()).into())
+}
+
+fn subtitle_error(e: &anyhow::Error) -> HttpResponse {
+    if e.downcast_ref::<TrackNotFound>().is_some() {
+        HttpResponse::NotFound().body(e.to_string())
+    } else {
+        HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()}))
+    }
+}
+
+#[routes]
+#[get("/subtitle/{season_id}/{sort}/{lang}.m3u8")]
+#[get("/ugc/subtitle/{bvid}/{page}/{lang}.m3u8")]
+pub async fn subtitle_playlist(
+    path: web::Path<(String, String, String)>,
+    q: web::Query<HashMap<String, String>>,
+    data: web::Data<crate::AppState>,
+    req: HttpRequest,
+) -> impl Responder {
+    let (season_id, sort, lang) = path.into_inner();
+    let file = format!("{}.m3u8", lang);
+    if let Some(resp) = hls::redirect_to_ep(&data.client, &season_id, &sort, &file, &req).await {
+        return resp;
+    }
+    let format = q.get("format").map(String::as_str).unwrap_or("fmp4");
+    let result = async {
+        let track = find_track(&data.client, &season_id, &sort, &lang).await?;
+        fetch_cues(&data.client, &track).await
+    }
+    .await;
+    match result {
+        Ok(cues) => HttpResponse::Ok()
+            .insert_header(("Content-Type", "application/vnd.apple.mpegurl"))
+            .insert_header(("Cache-Control", "no-store"))
+            .body(render_playlist(&cues, &lang, format)),
+        Err(e) => subtitle_error(&e),
+    }
+}
+
+/// 单独下载字幕：`?format=ts` 时附带与 TS 分片对齐的 X-TIMESTAMP-MAP
+#[routes]
+#[get("/subtitle/{season_id}/{sort}/{lang}.vtt")]
+#[get("/ugc/subtitle/{bvid}/{page}/{lang}.vtt")]
+pub async fn subtitle_vtt(
+    path: web::Path<(String, String, String)>,
+    q: web::Query<HashMap<String, String>>,
+    data: web::Data<crate::AppState>,
+    req: HttpRequest,
+) -> impl Responder {
+    let (season_id, sort, lang) = path.into_inner();
+    let file = format!("{}.vtt", lang);
+    if let Some(resp) = hls::redirect_to_ep(&data.client, &season_id, &sort, &file, &req).await {
+        return resp;
+    }
+    let mpegts = q.get("format").is_some_and(|f| f == "ts");
+    let result = async {
+        let track = find_track(&data.client, &season_id, &sort, &lang).await?;
+        fetch_cues(&data.client, &track).await
+    }
+    .await;
+    match result {
+        Ok(cues) => HttpResponse::Ok()
+            .insert_header(("Content-Type", "text/vtt; charset=utf-8"))
+            .insert_header(("Cache-Control", "public, max-age=3600"))
+            .body(render_vtt(&cues, mpegts)),
+        Err(e) => subtitle_error(&e),
+    }
+}
@@ -0,0 +1,491 @@
+//! FFmpeg 任务监管：按 (season_id, sort, variant) 登记每个转码任务，
+//! 以配置的并发上限排队启动，长时间无人拉取分片的任务自动终止，并通过 `/jobs` 查看状态。
+
+use actix_web::{HttpRequest, HttpResponse, Responder, get};
+use anyhow::{Result, anyhow};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::process::Child;
+use tokio::sync::{Semaphore, oneshot};
+
+use crate::cache::{self, JobStatus, Manifest};
+use crate::config;
+use crate::hls::{self, SegmentFormat};
+
+/// 任务标识：一集的一个变体
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct JobKey {
+    pub season_id: String,
+    pub sort: String,
+    pub variant: String,
+}
+
+impl fmt::Display for JobKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}/{}", self.season_id, self.sort, self.variant)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    /// 等待并发名额
+    Queued,
+    Running,
+}
+
+struct Job {
+    id: u64,
+    work_dir: PathBuf,
+    format: SegmentFormat,
+    state: JobState,
+    pid: Option<u32>,
+    start_number: u64,
+    total_segments: u64,
+    started_at: SystemTime,
+    last_access: Instant,
+    /// 丢弃发送端即终止对应进程
+    kill: Option<oneshot::Sender<()>>,
+}
+
+impl Job {
+    fn cursor(&self) -> Cursor {
+        Cursor {
+            work_dir: self.work_dir.clone(),
+            format: self.format,
+            start_number: self.start_number,
+        }
+    }
+}
+
+/// 计算任务进度所需的信息：在 REGISTRY 锁内复制，释放锁后再检查分片文件，
+/// 避免文件系统调用阻塞其它请求
+struct Cursor {
+    work_dir: PathBuf,
+    format: SegmentFormat,
+    start_number: u64,
+}
+
+impl Cursor {
+    /// 从起点开始连续已生成的分片，返回下一个待生成的分片序号
+    fn head(&self) -> u64 {
+        // 复制视频的任务先切出小分片，合并后才算生成；FFmpeg 刚退出时最后几个分片在这里合并
+        hls::merge_parts(&self.work_dir, self.format);
+        let mut n = self.start_number;
+        while self
+            .work_dir
+            .join(hls::segment_name(self.format, n))
+            .exists()
+        {
+            n += 1;
+        }
+        n
+    }
+}
+
+static REGISTRY: Lazy<Mutex<HashMap<JobKey, Job>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+static NEXT_JOB_ID: AtomicU64 = AtomicU64::new(1);
+
+/// 并发名额；max_concurrent_jobs = 0 表示不限制
+static SLOTS: Lazy<Arc<Semaphore>> = Lazy::new(|| {
+    let max = config::get().hls.max_concurrent_jobs;
+    Arc::new(Semaphore::new(if max == 0 {
+        Semaphore::MAX_PERMITS
+    } else {
+        max
+    }))
+});
+
+/// 登记一个从 `start_number` 开始的新任务。
+/// 若已有任务的进度能在 `gap` 个分片内覆盖该位置则返回 None（等待即可）；
+/// 否则替换（并终止）旧任务，返回新任务 id。
+pub fn claim(
+    key: &JobKey,
+    work_dir: &Path,
+    format: SegmentFormat,
+    start_number: u64,
+    gap: u64,
+) -> Result<Option<u64>> {
+    // 在锁外检查已有任务的进度；检查期间任务被替换时按新任务重新判断
+    let mut jobs = loop {
+        let existing = REGISTRY
+            .lock()
+            .map_err(|_| anyhow!("任务表锁已损坏"))?
+            .get(key)
+            .map(|job| (job.id, job.cursor()));
+        let head = existing
+            .as_ref()
+            .filter(|(_, cursor)| start_number >= cursor.start_number)
+            .map(|(_, cursor)| cursor.head());
+        let mut jobs = REGISTRY.lock().map_err(|_| anyhow!("任务表锁已损坏"))?;
+        if jobs.get(key).map(|j| j.id) != existing.map(|(id, _)| id) {
+            continue;
+        }
+        if let (Some(head), Some(job)) = (head, jobs.get_mut(key))
+            && start_number <= head + gap
+        {
+            job.last_access = Instant::now();
+            return Ok(None);
+        }
+        break jobs;
+    };
+    let id = NEXT_JOB_ID.fetch_add(1, Ordering::Relaxed);
+    let previous = jobs.insert(
+        key.clone(),
+        Job {
+            id,
+            work_dir: work_dir.to_path_buf(),
+            format,
+            state: JobState::Queued,
+            pid: None,
+            start_number,
+            total_segments: 0,
+            started_at: SystemTime::now(),
+            last_access: Instant::now(),
+            kill: None,
+        },
+    );
+    if let Some(prev) = previous {
+        log::info!(
+            "分片 {} 超出转码进度，重启 FFmpeg: {} (原起点 {})",
+            start_number,
+            key,
+            prev.start_number
+        );
+        // prev 在此被丢弃，kill 发送端随之关闭，旧进程被终止
+    }
+    Ok(Some(id))
+}
+
+/// 放弃尚未启动的任务（如获取播放地址失败）
+pub fn abandon(key: &JobKey, id: u64) {
+    if let Ok(mut jobs) = REGISTRY.lock()
+        && jobs.get(key).is_some_and(|j| j.id == id)
+    {
+        jobs.remove(key);
+    }
+}
+
+fn is_current(key: &JobKey, id: u64) -> bool {
+    REGISTRY
+        .lock()
+        .map(|jobs| jobs.get(key).is_some_and(|j| j.id == id))
+        .unwrap_or(false)
+}
+
+/// 长时间没有产出新分片即视为卡住
+const STALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 单次 FFmpeg 运行的结果
+enum Outcome {
+    Complete,
+    Failed(String),
+    Stalled,
+    Killed,
+}
+
+/// 等待并发名额后启动任务，并在后台监视进程直到退出或被终止。
+/// 排队期间任务被替换或因空闲被清理时直接放弃启动。
+/// FFmpeg 异常退出或卡住时，以 `spawn(attempt, 起始分片)` 从已生成的进度处重试，
+/// 最多 `attempts` 次（每次由调用方换用下一个 CDN 镜像）。
+pub async fn launch<F>(key: JobKey, id: u64, total_segments: u64, attempts: usize, mut spawn: F)
+where
+    F: FnMut(usize, u64) -> Result<Child>,
+{
+    let permit = match SLOTS.clone().acquire_owned().await {
+        Ok(p) => p,
+        Err(_) => return,
+    };
+    if !is_current(&key, id) {
+        return;
+    }
+    let work_dir = match REGISTRY.lock() {
+        Ok(jobs) => jobs
+            .get(&key)
+            .map(|j| j.work_dir.clone())
+            .unwrap_or_default(),
+        Err(_) => return,
+    };
+    let start_number = start_number(&key).unwrap_or(0);
+    let (kill_tx, mut kill_rx) = oneshot::channel::<()>();
+    let mut kill_tx = Some(kill_tx);
+    let lock_path = work_dir.join(".lock");
+    let mut resume_at = start_number;
+    let mut outcome = Outcome::Failed("未启动".into());
+    let mut spawned = false;
+
+    for attempt in 0..attempts.max(1) {
+        let mut child = match spawn(attempt, resume_at) {
+            Ok(c) => c,
+            Err(e) => {
+                log::error!("启动 FFmpeg 失败: {} {e}", key);
+                outcome = Outcome::Failed(format!("启动 FFmpeg 失败: {e}"));
+                continue;
+            }
+        };
+        spawned = true;
+        {
+            let Ok(mut jobs) = REGISTRY.lock() else {
+                return;
+            };
+            match jobs.get_mut(&key) {
+                Some(job) if job.id == id => {
+                    job.state = JobState::Running;
+                    job.total_segments = total_segments;
+                    job.pid = child.id();
+                    job.started_at = SystemTime::now();
+                    if let Some(tx) = kill_tx.take() {
+                        job.kill = Some(tx);
+                    }
+                }
+                // 启动期间已被替换：丢弃 kill_tx，下面的 select 会立即终止进程
+                _ => drop(kill_tx.take()),
+            }
+        }
+        // 锁文件与任务清单标记该目录有 FFmpeg 正在写入
+        let _ = fs::write(&lock_path, id.to_string());
+        cache::write_manifest(
+            &work_dir,
+            &Manifest {
+                status: JobStatus::Running,
+                job_id: id,
+                pid: child.id(),
+                start_number,
+                started_at: cache::now_secs(),
+                finished_at: None,
+                error: None,
+            },
+        );
+
+        outcome = tokio::select! {
+            status = child.wait() => match status {
+                // 正常退出但分片没有产出完整（如上游连接中途断开）同样按失败重试
+                Ok(s) if s.success() => match progress(&key, id) {
+                    Some(done) if done < total_segments => Outcome::Failed(format!(
+                        "FFmpeg 提前结束: 已生成 {}/{} 个分片",
+                        done, total_segments
+                    )),
+                    _ => Outcome::Complete,
+                },
+                Ok(s) => Outcome::Failed(format!("FFmpeg 退出: {}", s)),
+                Err(e) => Outcome::Failed(format!("等待 FFmpeg 退出失败: {e}")),
+            },
+            _ = &mut kill_rx => Outcome::Killed,
+            _ = wait_stalled(&key, id) => Outcome::Stalled,
+        };
+        match &outcome {
+            Outcome::Complete => {
+                log::info!("FFmpeg 结束，HLS 生成完成: {}", key);
+                break;
+            }
+            Outcome::Killed => {
+                let _ = child.kill().await;
+                log::info!("FFmpeg 已终止: {}", key);
+                break;
+            }
+            Outcome::Failed(e) => log::error!("{} ({})", e, key),
+            Outcome::Stalled => {
+                let _ = child.kill().await;
+                log::warn!("FFmpeg {}s 无新分片产出: {}", STALL_TIMEOUT.as_secs(), key);
+            }
+        }
+        // 失败或卡住：从已生成的进度处换下一个镜像重试
+        resume_at = progress(&key, id).unwrap_or(resume_at);
+        if attempt + 1 < attempts {
+            log::info!(
+                "从分片 {} 处重试 ({}/{}): {}",
+                resume_at,
+                attempt + 2,
+                attempts,
+                key
+            );
+        }
+    }
+
+    drop(permit);
+    abandon(&key, id);
+    let (status, error) = match outcome {
+        Outcome::Complete => (JobStatus::Complete, None),
+        Outcome::Killed => (JobStatus::Stopped, None),
+        Outcome::Failed(e) => (JobStatus::Failed, Some(e)),
+        Outcome::Stalled => (JobStatus::Failed, Some("FFmpeg 无新分片产出".into())),
+    };
+    if spawned {
+        cache::finish_manifest(&work_dir, id, status, error);
+    } else {
+        cache::write_manifest(
+            &work_dir,
+            &Manifest {
+                status,
+                job_id: id,
+                pid: None,
+                start_number,
+                started_at: cache::now_secs(),
+                finished_at: Some(cache::now_secs()),
+                error,
+            },
+        );
+    }
+    // 仍是本任务写下的锁才清理，避免误删重启后新任务的锁
+    if fs::read_to_string(&lock_path).is_ok_and(|s| s == id.to_string()) {
+        let _ = fs::remove_file(&lock_path);
+    }
+}
+
+/// 任务当前的产出进度（下一个待生成的分片）
+fn progress(key: &JobKey, id: u64) -> Option<u64> {
+    let cursor = REGISTRY
+        .lock()
+        .ok()?
+        .get(key)
+        .filter(|j| j.id == id)
+        .map(Job::cursor)?;
+    Some(cursor.head())
+}
+
+/// 进度在 STALL_TIMEOUT 内没有推进时返回
+async fn wait_stalled(key: &JobKey, id: u64) {
+    let mut last = progress(key, id);
+    let mut since = Instant::now();
+    loop {
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        let now = progress(key, id);
+        if now != last {
+            last = now;
+            since = Instant::now();
+        } else if since.elapsed() >= STALL_TIMEOUT {
+            return;
+        }
+    }
+}
+
+/// 记录一次访问（拉取 playlist 或分片），用于空闲判断
+pub fn touch(key: &JobKey) {
+    if let Ok(mut jobs) = REGISTRY.lock()
+        && let Some(job) = jobs.get_mut(key)
+    {
+        job.last_access = Instant::now();
+    }
+}
+
+/// 当前进程内是否有该变体的任务（排队或运行中）
+pub fn is_active(key: &JobKey) -> bool {
+    REGISTRY
+        .lock()
+        .map(|jobs| jobs.contains_key(key))
+        .unwrap_or(false)
+}
+
+/// 所有登记中任务的工作目录，缓存淘汰时跳过
+pub fn active_dirs() -> HashSet<PathBuf> {
+    REGISTRY
+        .lock()
+        .map(|jobs| jobs.values().map(|j| j.work_dir.clone()).collect())
+        .unwrap_or_default()
+}
+
+/// 当前任务的起始分片
+pub fn start_number(key: &JobKey) -> Option<u64> {
+    REGISTRY
+        .lock()
+        .ok()
+        .and_then(|jobs| jobs.get(key).map(|j| j.start_number))
+}
+
+/// 后台定期终止空闲任务；idle_timeout_secs = 0 表示不自动终止
+pub fn spawn_reaper() {
+    let idle = config::get().hls.idle_timeout_secs;
+    if idle == 0 {
+        return;
+    }
+    let idle = Duration::from_secs(idle);
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(Duration::from_secs(5));
+        loop {
+            tick.tick().await;
+            let Ok(mut jobs) = REGISTRY.lock() else {
+                continue;
+            };
+            jobs.retain(|key, job| {
+                let keep = job.last_access.elapsed() < idle;
+                if !keep {
+                    log::info!("任务空闲超过 {}s，终止: {}", idle.as_secs(), key);
+                }
+                keep
+            });
+        }
+    });
+}
+
+#[derive(Serialize)]
+struct JobView {
+    key: String,
+    season_id: String,
+    sort: String,
+    variant: String,
+    state: JobState,
+    pid: Option<u32>,
+    start_number: u64,
+    /// 已生成到的分片序号（下一个待生成）
+    progress: u64,
+    total_segments: u64,
+    /// 启动时间（Unix 秒）
+    started_at: u64,
+    idle_secs: u64,
+}
+
+#[get("/jobs")]
+pub async fn jobs_endpoint(req: HttpRequest) -> impl Responder {
+    if let Some(denied) = crate::admin_guard(&req) {
+        return denied;
+    }
+    let snapshot: Vec<(JobView, Cursor)> = REGISTRY
+        .lock()
+        .map(|jobs| {
+            jobs.iter()
+                .map(|(key, job)| {
+                    let view = JobView {
+                        key: key.to_string(),
+                        season_id: key.season_id.clone(),
+                        sort: key.sort.clone(),
+                        variant: key.variant.clone(),
+                        state: job.state,
+                        pid: job.pid,
+                        start_number: job.start_number,
+                        progress: 0,
+                        total_segments: job.total_segments,
+                        started_at: job
+                            .started_at
+                            .duration_since(UNIX_EPOCH)
+                            .map(|d| d.as_secs())
+                            .unwrap_or(0),
+                        idle_secs: job.last_access.elapsed().as_secs(),
+                    };
+                    (view, job.cursor())
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    // 锁外检查分片文件
+    let mut list: Vec<JobView> = snapshot
+        .into_iter()
+        .map(|(mut view, cursor)| {
+            view.progress = cursor.head();
+            view
+        })
+        .collect();
+    list.sort_by(|a, b| a.key.cmp(&b.key));
+    HttpResponse::Ok().json(crate::ApiResult {
+        code: 0,
+        success: true,
+        message: String::new(),
+        data: list,
+    })
+}
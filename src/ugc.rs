@@ -0,0 +1,281 @@
+//! 普通视频（UGC 稿件）：通过 view 接口把 bvid 解析为每一 P 的 aid/cid，
+//! 以 bvid 代替 season_id、分 P 序号代替 sort 接入现有的 HLS 流水线。
+//! view 响应与番剧信息一样在内存中缓存 `[season] ttl_secs` 秒，同一 bvid 的并发请求合并为一次。
+
+use actix_web::{HttpRequest, HttpResponse, Responder, get, web};
+use anyhow::{Result, anyhow};
+use futures::future::{BoxFuture, FutureExt, Shared};
+use once_cell::sync::Lazy;
+use reqwest::Client;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::cache::now_secs;
+use crate::{
+    ApiResult, AppState, DetailData, DetailSourceItem, config, error_status, hls, map_error_code,
+};
+
+#[derive(Debug, Deserialize)]
+struct ViewResp {
+    code: i32,
+    #[serde(default)]
+    message: String,
+    data: Option<View>,
+}
+
+/// view 接口中用到的稿件信息
+#[derive(Debug, Deserialize)]
+pub struct View {
+    pub bvid: String,
+    pub aid: u64,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub pic: String,
+    #[serde(default)]
+    pub desc: String,
+    #[serde(default)]
+    pub pubdate: i64,
+    #[serde(default)]
+    pub pages: Vec<ViewPage>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ViewPage {
+    pub cid: u64,
+    /// 分 P 序号，从 1 开始
+    pub page: usize,
+    #[serde(default)]
+    pub part: String,
+}
+
+/// bvid 形如 BV1xx411c7mD：固定 12 位、BV 开头的字母数字
+pub fn is_bvid(id: &str) -> bool {
+    id.len() == 12 && id.starts_with("BV") && id.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+/// 缓存中的一条稿件信息
+struct Entry {
+    /// 获取时间（Unix 秒），用于判断缓存是否过期
+    fetched_at: u64,
+    view: Arc<View>,
+}
+
+type Pending = Shared<BoxFuture<'static, Result<Arc<View>, Arc<anyhow::Error>>>>;
+
+static VIEWS: Lazy<Mutex<HashMap<String, Entry>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+/// 正在进行的上游请求，后到的调用方等待同一个结果
+static INFLIGHT: Lazy<Mutex<HashMap<String, Pending>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+impl Entry {
+    fn is_fresh(&self) -> bool {
+        now_secs().saturating_sub(self.fetched_at) < config::get().season.ttl_secs
+    }
+}
+
+/// 获取稿件信息，优先使用缓存
+pub async fn fetch_view(client: &Client, bvid: &str) -> Result<Arc<View>> {
+    let cached = VIEWS.lock().ok().and_then(|map| {
+        map.get(bvid)
+            .filter(|e| e.is_fresh())
+            .map(|e| e.view.clone())
+    });
+    if let Some(view) = cached {
+        return Ok(view);
+    }
+    let pending = {
+        let mut inflight = INFLIGHT.lock().map_err(|_| anyhow!("稿件请求表锁已损坏"))?;
+        inflight
+            .entry(bvid.to_string())
+            .or_insert_with(|| {
+                let client = client.clone();
+                let bvid = bvid.to_string();
+                async move {
+                    let result = request_view(&client, &bvid)
+                        .await
+                        .map(|view| store(&bvid, view))
+                        .map_err(Arc::new);
+                    if let Ok(mut inflight) = INFLIGHT.lock() {
+                        inflight.remove(&bvid);
+                    }
+                    result
+                }
+                .boxed()
+                .shared()
+            })
+            .clone()
+    };
+    pending.await.map_err(|e| anyhow!("{e:#}"))
+}
+
+/// 写入内存缓存，`ttl_secs = 0` 时不缓存
+fn store(bvid: &str, view: View) -> Arc<View> {
+    let view = Arc::new(view);
+    if config::get().season.ttl_secs == 0 {
+        return view;
+    }
+    if let Ok(mut map) = VIEWS.lock() {
+        map.retain(|_, e| e.is_fresh());
+        map.insert(
+            bvid.to_string(),
+            Entry {
+                fetched_at: now_secs(),
+                view: view.clone(),
+            },
+        );
+    }
+    view
+}
+
+async fn request_view(client: &Client, bvid: &str) -> Result<View> {
+    let mut url = reqwest::Url::parse("https://api.bilibili.com/x/web-interface/view")?;
+    url.query_pairs_mut().append_pair("bvid", bvid);
+    let resp: ViewResp = client
+        .get(url)
+        .header("Referer", "https://www.bilibili.com")
+        .send()
+        .await?
+        .json()
+        .await?;
+    if resp.code != 0 {
+        return Err(anyhow!(
+            "获取稿件信息失败 bvid={} code={} {}",
+            bvid,
+            resp.code,
+            resp.message
+        ));
+    }
+    resp.data
+        .ok_or_else(|| anyhow!("video not found bvid={}", bvid))
+}
+
+/// 解析某一 P 的 (aid, cid)
+pub async fn page_ids(client: &Client, bvid: &str, page: usize) -> Result<(u64, u64)> {
+    let view = fetch_view(client, bvid).await?;
+    let cid = view
+        .pages
+        .iter()
+        .find(|p| p.page == page)
+        .map(|p| p.cid)
+        .ok_or_else(|| anyhow!("page index out of range"))?;
+    Ok((view.aid, cid))
+}
+
+/// Unix 时间戳（秒）对应的公历年份（UTC+8）
+pub fn year_of(ts: i64) -> String {
+    if ts <= 0 {
+        return String::new();
+    }
+    // Howard Hinnant 的 civil_from_days
+    let days = (ts + 8 * 3600).div_euclid(86400) + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    year.to_string()
+}
+
+async fn fetch_ugc_full(client: &Client, bvid: &str, public_base: &str) -> Result<DetailData> {
+    let view = fetch_view(client, bvid).await?;
+    let multi = view.pages.len() > 1;
+    let path_of = |p: &ViewPage| format!("/ugc/hls/{}/{}/index.m3u8", view.bvid, p.page);
+    let sources = view
+        .pages
+        .iter()
+        .map(|p| DetailSourceItem {
+            name: if multi || p.part.is_empty() {
+                format!("P{} {}", p.page, p.part).trim_end().to_string()
+            } else {
+                p.part.clone()
+            },
+            sort: p.page,
+            ep_id: None,
+            aid: view.aid,
+            cid: p.cid,
+            bvid: view.bvid.clone(),
+            m3u8: format!("{}{}", public_base.trim_end_matches('/'), path_of(p)),
+            path: path_of(p),
+            skip: hls::EpisodeSkip::default(),
+        })
+        .collect();
+    Ok(DetailData {
+        id: view.bvid.clone(),
+        title: view.title.clone(),
+        cover: view.pic.clone(),
+        description: view.desc.clone(),
+        year: year_of(view.pubdate),
+        status: String::new(),
+        type_field: "视频".to_string(),
+        sources,
+        dubs: Vec::new(),
+        sections: Vec::new(),
+    })
+}
+
+#[get("/ugc/detail/{bvid}")]
+pub async fn ugc_detail_endpoint(
+    path: web::Path<(String,)>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let bvid = path.into_inner().0;
+    if !is_bvid(&bvid) {
+        return HttpResponse::BadRequest().json(ApiResult {
+            code: 400,
+            success: false,
+            message: "bvid 格式不正确".into(),
+            data: serde_json::json!({}),
+        });
+    }
+    match fetch_ugc_full(&data.client, &bvid, &data.public_base).await {
+        Ok(detail) => HttpResponse::Ok().json(ApiResult {
+            code: 0,
+            success: true,
+            message: String::new(),
+            data: detail,
+        }),
+        Err(e) => {
+            log::error!("ugc detail error bvid={} err={e:#}", bvid);
+            let (code, msg) = map_error_code(&e);
+            HttpResponse::build(error_status(code)).json(ApiResult {
+                code,
+                success: false,
+                message: msg,
+                data: serde_json::json!({}),
+            })
+        }
+    }
+}
+
+#[get("/ugc/html/{bvid}")]
+pub async fn ugc_html_endpoint(
+    path: web::Path<(String,)>,
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> impl Responder {
+    let bvid = path.into_inner().0;
+    if !is_bvid(&bvid) {
+        return HttpResponse::BadRequest()
+            .insert_header(("Content-Type", "text/plain; charset=utf-8"))
+            .body("bvid 格式不正确");
+    }
+    match fetch_ugc_full(&data.client, &bvid, &data.public_base).await {
+        Ok(detail) => {
+            let base = crate::request_base(&data.public_base, &req);
+            let page = crate::render_detail_html(&detail, &base);
+            HttpResponse::Ok()
+                .insert_header(("Content-Type", "text/html; charset=utf-8"))
+                .body(page)
+        }
+        Err(e) => {
+            log::error!("ugc html error bvid={} err={e:#}", bvid);
+            let (code, msg) = map_error_code(&e);
+            HttpResponse::build(error_status(code))
+                .insert_header(("Content-Type", "text/plain; charset=utf-8"))
+                .body(format!("获取稿件失败: {}", msg))
+        }
+    }
+}